@@ -0,0 +1,62 @@
+//! Times how long the challenge takes to reach its first prompt with tracing
+//! off and with tracing on, and how much faster the untraced run is.
+//!
+//! `cargo run --release --example bench -- [BIN] [RUNS]`
+use std::{
+  env,
+  time::{Duration, Instant}
+};
+use vm::vm::VM;
+
+///Timings of one configuration over every run.
+struct Timing {
+  best:Duration,
+  mean:Duration,
+  cycles:u64
+}
+
+///Run the program to its first prompt `runs` times, tracing to a temporary
+/// file if `traced` is set.
+fn time(bin:&str, runs:u32, traced:bool) -> Timing {
+  let trace = env::temp_dir().join(format!("vm-bench-{}.trace", std::process::id()));
+  let mut best = Duration::MAX;
+  let mut total = Duration::ZERO;
+  let mut cycles = 0;
+  for _ in 0..runs {
+    let mut vm = VM::new();
+    vm.config.bin = bin.into();
+    vm.load_new().expect("could not load the program binary");
+    if traced {
+      vm.trace_to(&trace).expect("could not create the trace file");
+    }
+
+    let start = Instant::now();
+    vm.run_until_input(usize::MAX);
+    let elapsed = start.elapsed();
+    best = best.min(elapsed);
+    total += elapsed;
+    cycles = vm.cycles;
+  }
+  let _ = std::fs::remove_file(&trace);
+  Timing {
+    best,
+    mean:total / runs.max(1),
+    cycles
+  }
+}
+
+fn main() {
+  let mut args = env::args().skip(1);
+  let bin = args.next().unwrap_or_else(|| String::from("challenge.bin"));
+  let runs = args.next().map_or(50, |runs| runs.parse::<u32>().expect("RUNS must be a number"));
+
+  let untraced = time(&bin, runs, false);
+  let traced = time(&bin, runs, true);
+
+  println!("{} instructions to the first prompt, {runs} runs each", untraced.cycles);
+  for (name, timing) in [("untraced", &untraced), ("traced", &traced)] {
+    let rate = timing.cycles as f64 / timing.best.as_secs_f64() / 1e6;
+    println!("{name:>8}: best {:?}, mean {:?}, {rate:.1}M instructions/s", timing.best, timing.mean);
+  }
+  println!("untraced runs are {:.1}x faster", traced.best.as_secs_f64() / untraced.best.as_secs_f64());
+}
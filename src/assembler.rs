@@ -0,0 +1,317 @@
+use crate::{
+  errors::VMErrors,
+  instruction::REGISTERS,
+  vm::{OpCode, WORDSIZE}
+};
+use eyre::Result;
+use std::{collections::HashMap, iter::Peekable, str::Chars};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Word(String),
+  Str(String),
+  Char(char)
+}
+
+///A line of source after labels have been removed.
+enum Item {
+  Instruction(OpCode, Vec<Token>),
+  Data(Vec<Token>)
+}
+
+///Assemble source text into the words of a program.
+///
+///Each line holds an optional `label:`, then an [`OpCode`] mnemonic with its
+/// operands or a directive. Operands are separated by whitespace or commas
+/// and may be numbers, registers `r0`..`r7`, character literals like `'a'`
+/// or labels. `.data` emits its operands as words and `.string` emits the
+/// characters of its strings. Everything after a `;` is a comment. Numeric
+/// labels such as the addresses in a disassembly are ignored.
+pub fn assemble(src:&str) -> Result<Vec<u16>> {
+  //First pass finds the address of every label
+  let mut labels = HashMap::new();
+  let mut items = Vec::new();
+  let mut addr = 0;
+
+  for (num, line) in src.lines().enumerate() {
+    let num = num + 1;
+    let mut tokens = tokenize(line).map_err(|msg| assembly_error(num, msg))?;
+
+    //Strip the labels from the front of the line
+    while let Some(Token::Word(word)) = tokens.first() {
+      let Some(label) = word.strip_suffix(':')
+      else {
+        break;
+      };
+
+      if label.parse::<usize>().is_err() {
+        if !is_identifier(label) || register(label).is_some() {
+          return Err(assembly_error(num, format!("`{label}` is not a valid label")));
+        }
+        if labels.insert(label.to_string(), addr).is_some() {
+          return Err(assembly_error(num, format!("label `{label}` is defined twice")));
+        }
+      }
+      tokens.remove(0);
+    }
+
+    if tokens.is_empty() {
+      continue;
+    }
+
+    let name = match tokens.remove(0) {
+      Token::Word(name) => name,
+      token => return Err(assembly_error(num, format!("expected a mnemonic or directive, found {token:?}")))
+    };
+
+    let item = match name.to_lowercase().as_str() {
+      ".data" => Item::Data(tokens),
+      ".string" => {
+        if let Some(token) = tokens.iter().find(|token| !matches!(token, Token::Str(_))) {
+          return Err(assembly_error(num, format!(".string only takes strings, found {token:?}")));
+        }
+        Item::Data(tokens)
+      }
+      mnemonic => {
+        let op = OpCode::from_name(mnemonic).ok_or_else(|| assembly_error(num, format!("unknown mnemonic `{name}`")))?;
+        if tokens.len() != op.arity() {
+          return Err(assembly_error(num, format!("{op:?} takes {} operands, found {}", op.arity(), tokens.len())));
+        }
+        Item::Instruction(op, tokens)
+      }
+    };
+
+    addr += match &item {
+      Item::Instruction(op, _) => op.arity() + 1,
+      Item::Data(tokens) => tokens.iter().map(data_len).sum()
+    };
+    items.push((num, item));
+  }
+
+  //Second pass emits the words
+  let mut words = Vec::with_capacity(addr);
+  for (num, item) in items {
+    match item {
+      Item::Instruction(op, tokens) => {
+        words.push(op as u16);
+        for token in tokens {
+          let word = operand(&token, &labels).map_err(|msg| assembly_error(num, msg))?;
+          words.push(word);
+        }
+      }
+      Item::Data(tokens) => {
+        for token in tokens {
+          match token {
+            Token::Str(s) => words.extend(s.chars().map(|c| c as u16)),
+            Token::Word(word) if word.parse::<u16>().is_ok() => words.push(word.parse::<u16>().unwrap()),
+            token => words.push(operand(&token, &labels).map_err(|msg| assembly_error(num, msg))?)
+          }
+        }
+      }
+    }
+  }
+
+  Ok(words)
+}
+
+///Encode the words of a program as a little-endian binary which
+/// [`VM::load_bin`](crate::vm::VM::load_bin) can run.
+pub fn to_bin(words:&[u16]) -> Vec<u8> {
+  words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn assembly_error(line:usize, msg:String) -> eyre::Report {
+  VMErrors::Assembly { line, msg }.into()
+}
+
+///Returns the index of a register name `r0`..`r7`.
+fn register(word:&str) -> Option<u16> {
+  let index = word.strip_prefix(['r', 'R'])?.parse::<u16>().ok()?;
+  (index < REGISTERS).then_some(index)
+}
+
+fn is_identifier(word:&str) -> bool {
+  let mut chars = word.chars();
+  matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+///Returns the number of words a `.data` token occupies.
+fn data_len(token:&Token) -> usize {
+  match token {
+    Token::Str(s) => s.chars().count(),
+    _ => 1
+  }
+}
+
+///Encode a single operand.
+fn operand(token:&Token, labels:&HashMap<String, usize>) -> Result<u16, String> {
+  match token {
+    Token::Char(c) if (*c as u32) < WORDSIZE as u32 => Ok(*c as u16),
+    Token::Char(c) => Err(format!("{c:?} does not fit in 15 bits")),
+    Token::Str(s) => Err(format!("string {s:?} can only be used with .data or .string")),
+    Token::Word(word) => {
+      if let Some(index) = register(word) {
+        return Ok(WORDSIZE + index);
+      }
+      if let Some(addr) = labels.get(word) {
+        return Ok(*addr as u16);
+      }
+
+      let val = match word.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => word.parse::<u16>()
+      }
+      .map_err(|_| format!("`{word}` is not a number, register or label"))?;
+
+      if val >= WORDSIZE {
+        return Err(format!("literal `{val}` is too large. Use r0..r7 for registers"));
+      }
+      Ok(val)
+    }
+  }
+}
+
+///Split a line into [`Token`]s. Stops at a comment.
+fn tokenize(line:&str) -> Result<Vec<Token>, String> {
+  let mut tokens = Vec::new();
+  let mut chars = line.chars().peekable();
+
+  while let Some(&c) = chars.peek() {
+    match c {
+      ';' => break,
+      c if c.is_whitespace() || c == ',' => {
+        chars.next();
+      }
+      '"' => {
+        chars.next();
+        let mut s = String::new();
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some('\\') => s.push(escape(&mut chars)?),
+            Some(c) => s.push(c),
+            None => return Err(String::from("unterminated string"))
+          }
+        }
+        tokens.push(Token::Str(s));
+      }
+      '\'' => {
+        chars.next();
+        let c = match chars.next() {
+          Some('\\') => escape(&mut chars)?,
+          Some(c) => c,
+          None => return Err(String::from("unterminated character"))
+        };
+        if chars.next() != Some('\'') {
+          return Err(String::from("unterminated character"));
+        }
+        tokens.push(Token::Char(c));
+      }
+      _ => {
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || c == ',' || c == ';' {
+            break;
+          }
+          word.push(c);
+          chars.next();
+        }
+        tokens.push(Token::Word(word));
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+///Read the rest of an escape sequence. Accepts the escapes Rust's [`Debug`]
+/// output for [`char`] produces.
+fn escape(chars:&mut Peekable<Chars>) -> Result<char, String> {
+  match chars.next() {
+    Some('n') => Ok('\n'),
+    Some('r') => Ok('\r'),
+    Some('t') => Ok('\t'),
+    Some('0') => Ok('\0'),
+    Some('u') => {
+      if chars.next() != Some('{') {
+        return Err(String::from("expected `{` after \\u"));
+      }
+      let hex = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+      u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or_else(|| format!("invalid escape \\u{{{hex}}}"))
+    }
+    Some(c @ ('\\' | '\'' | '"')) => Ok(c),
+    Some(c) => Err(format!("unknown escape \\{c}")),
+    None => Err(String::from("unterminated escape"))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{assemble, to_bin};
+  use crate::{
+    disassembler::{disassemble, words},
+    io::BufferOutput,
+    vm::VM
+  };
+  use std::fs;
+
+  const HELLO:&str = "
+    ; Print the string at `msg` one character at a time
+          set r0 msg
+    loop: rmem r1 r0
+          jf r1 done
+          out r1
+          add r0 r0 1
+          jmp loop
+    done: out '\\n'
+          halt
+    msg:  .string \"hello, world\"
+          .data 0
+  ";
+
+  #[test]
+  fn run_program() {
+    let words = assemble(HELLO).unwrap();
+    let path = std::env::temp_dir().join("vm_assembler_test.bin");
+    fs::write(&path, to_bin(&words)).unwrap();
+
+    let out = BufferOutput::new();
+    let mut vm = VM::new();
+    vm.set_output(out.clone());
+    vm.load_bin(&path).unwrap();
+    vm.run().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(out.contents(), "hello, world\n");
+  }
+
+  #[test]
+  fn round_trip() {
+    let words = assemble(HELLO).unwrap();
+
+    //Only disassemble the code since data would be listed as instructions
+    let listing = disassemble(&words, 0, 8);
+    assert!(listing.contains("    6: Jf r1 17"));
+    assert!(listing.contains("   17: Out '\\n'"));
+
+    let reassembled = assemble(&listing).unwrap();
+    assert_eq!(reassembled, words[..reassembled.len()]);
+  }
+
+  #[test]
+  fn round_trip_challenge() {
+    let words = words(&fs::read("challenge.bin").unwrap());
+    let listing = disassemble(&words, 0, words.len());
+    assert_eq!(assemble(&listing).unwrap(), words);
+  }
+
+  #[test]
+  fn errors() {
+    assert!(assemble("foo r0").is_err());
+    assert!(assemble("set r0").is_err());
+    assert!(assemble("set r8 1").is_err());
+    assert!(assemble("jmp nowhere").is_err());
+    assert!(assemble("a: noop\na: noop").is_err());
+    assert!(assemble("out 32768").is_err());
+    assert!(assemble(".string 'a'").is_err());
+  }
+}
@@ -0,0 +1,354 @@
+use crate::{
+  config::VmConfig,
+  coredump::CoreDump,
+  disassembler::disassemble,
+  helpers::{coin_order, find_r7, vault},
+  profile::Profiler,
+  save::{is_slot_name, SaveFormat},
+  script::Script,
+  transcript::{verify_output, Transcript},
+  vm::{RunStatus, VM, WORDSIZE}
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use eyre::Result;
+use std::{
+  fs,
+  path::{Path, PathBuf}
+};
+
+///Runs the Synacor challenge VM.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+  ///Config file setting the paths the VM uses. Defaults to `vm.json` in
+  /// `SYNACOR_ROOT` or the working directory
+  #[arg(long, global = true)]
+  pub config:Option<PathBuf>,
+  ///The program binary a new game loads
+  #[arg(long, global = true)]
+  pub bin:Option<PathBuf>,
+  ///Directory the save slots are kept in
+  #[arg(long, global = true)]
+  pub save_dir:Option<PathBuf>,
+  ///Format save slots are written in. Slots of either format can be loaded
+  #[arg(long, global = true)]
+  pub save_format:Option<SaveFormat>,
+  ///Log every executed instruction to this file
+  #[arg(long, global = true)]
+  pub trace:Option<PathBuf>,
+  ///File `*mem` writes its listing to
+  #[arg(long, global = true)]
+  pub console:Option<PathBuf>,
+  ///File `*path` writes the path through the vault to
+  #[arg(long, global = true)]
+  pub vault_path:Option<PathBuf>,
+  ///File a core dump is written to when the VM faults
+  #[arg(long, global = true, value_name = "FILE")]
+  pub core_file:Option<PathBuf>,
+  ///Stop after executing this many instructions
+  #[arg(long, global = true)]
+  pub max_cycles:Option<u64>,
+  ///Count the executed instructions and print the hottest addresses, opcodes
+  /// and functions at exit
+  #[arg(long, global = true)]
+  pub profile:bool,
+  ///Inspect the core dump of a VM which faulted. The same as the `core`
+  /// subcommand
+  #[arg(long, value_name = "FILE")]
+  pub core:Option<PathBuf>,
+  #[command(subcommand)]
+  pub command:Option<Cmd>
+}
+
+///How a game is started by `run` and `debug`.
+#[derive(Debug, Clone, PartialEq, clap::Args)]
+pub struct Game {
+  ///Save slot to resume
+  #[arg(long, default_value = "sync_save", value_parser = slot_name, conflicts_with = "new")]
+  pub slot:String,
+  ///Start a new game instead of resuming a save
+  #[arg(long)]
+  pub new:bool,
+  ///Start a new game and record the input to this file and the output to
+  /// the file with `.out` appended. Replays start from a new game
+  #[arg(long, conflicts_with = "slot")]
+  pub record:Option<PathBuf>
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Cmd {
+  ///Play the game without the debugger
+  Run(Game),
+  ///Play the game with the debugger. The default
+  Debug(Game),
+  ///Print the program binary's instructions
+  Disasm {
+    ///Word address to start at
+    #[arg(long, default_value_t = 0)]
+    start:usize,
+    ///Number of instructions to print. Defaults to the whole program
+    #[arg(long)]
+    count:Option<usize>
+  },
+  ///Replay a transcript from the start of a new game
+  Replay {
+    transcript:PathBuf,
+    ///Fail unless the output matches this output log
+    #[arg(long)]
+    expect:Option<PathBuf>
+  },
+  ///Run a script of commands from the start of a new game
+  Script { script:PathBuf },
+  ///Print the solution to a puzzle
+  Solve { puzzle:Puzzle },
+  ///Search for the value of R7 which passes the teleporter's confirmation
+  TeleporterSearch {
+    ///Value of R7 to start searching from
+    #[arg(long, default_value_t = 1)]
+    from:u16
+  },
+  ///Inspect the core dump of a VM which faulted
+  Core { core:PathBuf }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Puzzle {
+  ///The order to place the coins in at the monument
+  Coins,
+  ///The path through the vault's grid
+  Vault
+}
+
+fn slot_name(name:&str) -> Result<String, String> {
+  match is_slot_name(name) {
+    true => Ok(name.to_string()),
+    false => Err(String::from("slot names may only contain letters, digits, `-` and `_`"))
+  }
+}
+
+impl Cli {
+  ///Build the [`VmConfig`] from the config file and environment, overridden
+  /// by the paths passed on the command line.
+  pub fn vm_config(&self) -> Result<VmConfig> {
+    Ok(self.override_config(VmConfig::load(self.config.as_deref())?))
+  }
+
+  ///Replace the paths in `config` with the ones passed on the command line.
+  fn override_config(&self, mut config:VmConfig) -> VmConfig {
+    let paths = [
+      (&self.bin, &mut config.bin),
+      (&self.save_dir, &mut config.save_dir),
+      (&self.trace, &mut config.trace),
+      (&self.console, &mut config.console),
+      (&self.vault_path, &mut config.vault_path)
+    ];
+    for (flag, path) in paths {
+      if let Some(flag) = flag {
+        *path = flag.clone();
+      }
+    }
+    if let Some(save_format) = self.save_format {
+      config.save_format = save_format;
+    }
+    if let Some(core) = &self.core_file {
+      config.core = Some(core.clone());
+    }
+    config
+  }
+
+  ///Create a [`VM`] using the paths and limits passed on the command line.
+  fn vm(&self) -> Result<VM> {
+    let mut vm = VM::new();
+    vm.config = self.vm_config()?;
+    vm.cycle_limit = self.max_cycles;
+    if self.profile {
+      vm.profiler = Some(Profiler::default());
+    }
+    Ok(vm)
+  }
+
+  ///Turn on tracing to the configured trace file once the game is loaded if
+  /// `--trace` was passed.
+  fn trace(&self, vm:&mut VM) -> Result<()> {
+    if self.trace.is_some() {
+      vm.trace_to(vm.config.trace.clone())?;
+    }
+    Ok(())
+  }
+
+  ///Create a [`VM`] at the start of a new game.
+  fn new_game(&self) -> Result<VM> {
+    let mut vm = self.vm()?;
+    vm.load_new()?;
+    self.trace(&mut vm)?;
+    Ok(vm)
+  }
+
+  ///Run the subcommand.
+  pub fn run(self) -> Result<()> {
+    let command = match (self.command.clone(), self.core.clone()) {
+      (Some(_), Some(_)) => return Err(eyre::eyre!("--core cannot be used with a subcommand")),
+      (command, core) => command.or(core.map(|core| Cmd::Core { core }))
+    };
+    let command = command.unwrap_or_else(|| {
+      Cmd::Debug(Game {
+        slot:String::from("sync_save"),
+        new:false,
+        record:None
+      })
+    });
+
+    match command {
+      Cmd::Run(game) => self.play(&game, false),
+      Cmd::Debug(game) => self.play(&game, true),
+      Cmd::Disasm { start, count } => {
+        let vm = self.new_game()?;
+        print!("{}", disassemble(&vm.mem, start, count.unwrap_or(vm.mem.len())));
+        Ok(())
+      }
+      Cmd::Replay { transcript, expect } => self.replay(&transcript, expect.as_deref()),
+      Cmd::Script { script } => {
+        let script = fs::read_to_string(script)?.parse::<Script>()?;
+        let mut vm = self.new_game()?;
+        let result = vm.run_script(&script);
+        self.report(&mut vm);
+        result
+      }
+      Cmd::Solve { puzzle } => match puzzle {
+        Puzzle::Coins => {
+          let order = coin_order().ok_or_else(|| eyre::eyre!("No order of the coins solves the equation"))?;
+          order.iter().for_each(|coin| println!("use {coin}"));
+          Ok(())
+        }
+        Puzzle::Vault => {
+          let path = vault().get_shortest_path((6, 22), (1, 30)).ok_or_else(|| eyre::eyre!("The vault has no path to the door"))?;
+          path.iter().for_each(|step| println!("{step}"));
+          Ok(())
+        }
+      },
+      Cmd::TeleporterSearch { from } => {
+        let r7 = find_r7(from).ok_or_else(|| eyre::eyre!("No value of R7 from {from} to {} passes the check", WORDSIZE - 1))?;
+        println!("R7 = {r7}");
+        Ok(())
+      }
+      Cmd::Core { core } => {
+        CoreDump::read(core)?.post_mortem();
+        Ok(())
+      }
+    }
+  }
+
+  ///Resume a save or start a new game and play it from the terminal.
+  fn play(&self, game:&Game, debug:bool) -> Result<()> {
+    let mut vm = self.vm()?;
+    match game.new || game.record.is_some() {
+      true => vm.load_new()?,
+      false => vm.load_or_new(&game.slot)?
+    }
+    self.trace(&mut vm)?;
+
+    if let Some(path) = &game.record {
+      let mut out = path.clone().into_os_string();
+      out.push(".out");
+      vm.record_transcript(Transcript::create(path)?.with_output(out)?);
+    }
+
+    let result = match debug {
+      true => vm.dbg_run(),
+      false => vm.run()
+    };
+    self.report(&mut vm);
+    result.map_err(|fault| eyre::eyre!(vm.fault_report(&fault)))
+  }
+
+  ///Print the profile report if profiling.
+  fn report(&self, vm:&mut VM) {
+    if self.profile {
+      vm.print_profile();
+    }
+  }
+
+  ///Replay a transcript without reading the terminal. Fails if the [`VM`]
+  /// faults or the output differs from the expected log.
+  fn replay(&self, transcript:&Path, expect:Option<&Path>) -> Result<()> {
+    let transcript = fs::read_to_string(transcript)?;
+    let mut vm = self.new_game()?;
+    let outcome = vm.replay(&transcript, usize::MAX);
+    print!("{}", outcome.output);
+    self.report(&mut vm);
+    if let RunStatus::Faulted(fault) = outcome.status {
+      return Err(eyre::eyre!(vm.fault_report(&fault)));
+    }
+
+    if let Some(log) = expect {
+      verify_output(&outcome.output, &fs::read_to_string(log)?)?;
+      eprintln!("The output matches {}", log.display());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Cli, Cmd, Game, Puzzle};
+  use crate::config::VmConfig;
+  use clap::Parser;
+  use std::{fs, path::PathBuf};
+
+  #[test]
+  fn parse() {
+    let cli = Cli::try_parse_from(["vm"]).unwrap();
+    assert_eq!((cli.bin, cli.save_dir, cli.command), (None, None, None));
+
+    let cli = Cli::try_parse_from(["vm", "run", "--slot", "vault", "--bin", "other.bin", "--max-cycles", "100"]).unwrap();
+    assert_eq!(cli.bin, Some(PathBuf::from("other.bin")));
+    assert_eq!(cli.max_cycles, Some(100));
+    assert_eq!(
+      cli.command,
+      Some(Cmd::Run(Game {
+        slot:String::from("vault"),
+        new:false,
+        record:None
+      }))
+    );
+
+    let cli = Cli::try_parse_from(["vm", "--trace", "trace.txt", "solve", "coins"]).unwrap();
+    assert_eq!(cli.trace, Some(PathBuf::from("trace.txt")));
+    assert!(!cli.profile);
+    assert!(Cli::try_parse_from(["vm", "replay", "--profile", "moves.txt"]).unwrap().profile);
+    assert_eq!(cli.command, Some(Cmd::Solve { puzzle:Puzzle::Coins }));
+
+    assert!(Cli::try_parse_from(["vm", "run", "--slot", "../escape"]).is_err());
+    assert!(Cli::try_parse_from(["vm", "run", "--slot", "a", "--new"]).is_err());
+    assert!(Cli::try_parse_from(["vm", "run", "--slot", "a", "--record", "moves.txt"]).is_err());
+    assert!(Cli::try_parse_from(["vm", "solve", "maze"]).is_err());
+
+    let cli = Cli::try_parse_from(["vm", "--core", "core.json"]).unwrap();
+    assert_eq!((cli.core, cli.command), (Some(PathBuf::from("core.json")), None));
+    assert!(Cli::try_parse_from(["vm", "--core", "core.json", "run"]).unwrap().run().is_err());
+  }
+
+  #[test]
+  fn precedence() {
+    let dir = std::env::temp_dir().join("vm_cli_precedence_test");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("vm.json");
+    fs::write(&path, r#"{"trace":"file.trace","console":"file.console","vault_path":"file.path","core":"file.core"}"#).unwrap();
+    let config = VmConfig::read(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    //The environment overrides the config file and the command line overrides both
+    let config = config.with_env(|name| match name {
+      "SYNACOR_CONSOLE" => Some(String::from("env.console")),
+      "SYNACOR_VAULT_PATH" => Some(String::from("env.path")),
+      _ => None
+    });
+    let cli = Cli::try_parse_from(["vm", "--vault-path", "cli.path", "--trace", "cli.trace", "--core-file", "cli.core", "run"]).unwrap();
+    let config = cli.override_config(config);
+    assert_eq!(config.trace, PathBuf::from("cli.trace"));
+    assert_eq!(config.console, PathBuf::from("env.console"));
+    assert_eq!(config.vault_path, PathBuf::from("cli.path"));
+    assert_eq!(config.core, Some(PathBuf::from("cli.core")));
+    assert_eq!(config.bin, dir.join("challenge.bin"));
+  }
+}
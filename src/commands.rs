@@ -0,0 +1,317 @@
+use crate::{
+  debugger::Watchpoint,
+  errors::VMErrors,
+  profile::ProfileSetting,
+  save::is_slot_name,
+  trace::TraceSetting,
+  vm::{OpCode, WORDSIZE}
+};
+use std::{
+  collections::BTreeSet,
+  fmt::Write,
+  num::NonZeroUsize,
+  str::{FromStr, SplitWhitespace}
+};
+
+///Usage and description of every system command, listed by `*help`.
+const USAGE:&[(&str, &str)] = &[
+  ("save [name]", "Save the game to a slot. Alias `s`"),
+  ("load [name]", "Load a save slot. Alias `ls`"),
+  ("saves", "List the save slots"),
+  ("delete <name>", "Delete a save slot"),
+  ("undo", "Undo the last command"),
+  ("rewind <n>", "Undo the last n commands"),
+  ("quit", "Save and quit. Alias `q`"),
+  ("rq", "Quit and start a new game"),
+  ("fq", "Quit without saving"),
+  ("dbg", "Toggle debug mode"),
+  ("print", "Toggle logging every instruction to the debug log"),
+  (
+    "trace [ops <op>... | pc <start> <end> | depth <n> | in <addr> | clear]",
+    "Show or set which instructions are logged. A filter given no values is removed"
+  ),
+  ("clear", "Clear the debug log"),
+  ("history [n]", "List the last n executed instructions and their changes. Defaults to 20"),
+  ("solve", "Run the coin solver"),
+  ("path", "Find the path through the vault"),
+  ("pause", "Stop at the debugger prompt before the next instruction"),
+  ("disasm [addr] [count]", "Disassemble memory. Defaults to the next 10 instructions"),
+  ("mem <addr> [count]", "Write the instructions at an address to the console file"),
+  ("reg <n> [value]", "Print or set a register"),
+  ("poke <addr> <value>...", "Write a run of words into memory starting at an address"),
+  ("patches", "List the register and memory changes applied this game"),
+  ("break <addr>", "Add a breakpoint"),
+  ("unbreak <addr>", "Remove a breakpoint"),
+  ("stepback [n]", "Reverse the last n instructions while debugging. Defaults to 1"),
+  ("reverse-continue", "Step back to the previous breakpoint while debugging"),
+  ("watch <reg N | mem ADDR [END]> [read | write | rw] [break | log]", "Add a watchpoint"),
+  ("unwatch <index>", "Remove a watchpoint"),
+  ("watches", "List the watchpoints"),
+  ("profile [start | stop | reset]", "Print the instructions executed most, or start, stop or restart counting them"),
+  ("help", "List the system commands")
+];
+
+///A system command. System commands are entered as lines starting with `*`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+  Save(Option<String>),
+  Load(Option<String>),
+  Saves,
+  DeleteSave(String),
+  Rewind(usize),
+  Quit,
+  RageQuit,
+  ForceQuit,
+  Debug,
+  Print,
+  Clear,
+  History(usize),
+  Solve,
+  Path,
+  Pause,
+  Disasm { addr:Option<usize>, count:Option<usize> },
+  Mem { addr:u16, count:u16 },
+  Reg { reg:u8, val:Option<u16> },
+  Poke { addr:u16, vals:Vec<u16> },
+  Patches,
+  Break(usize),
+  Unbreak(usize),
+  StepBack(usize),
+  ReverseContinue,
+  Watch(Watchpoint),
+  Unwatch(usize),
+  Watches,
+  Trace(TraceSetting),
+  Profile(ProfileSetting),
+  Help
+}
+
+impl FromStr for Command {
+  type Err = VMErrors;
+
+  ///Parse a line of input into a [`Command`]. The leading `*` and trailing
+  /// linebreak are optional.
+  fn from_str(s:&str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    let s = s.strip_prefix('*').unwrap_or(s);
+    let mut args = Args::new(s);
+
+    let cmd = match args.name {
+      "save" | "s" => Command::Save(args.slot()?),
+      "load" | "ls" => Command::Load(args.slot()?),
+      "saves" => Command::Saves,
+      "undo" => Command::Rewind(1),
+      "rewind" => Command::Rewind(args.required::<NonZeroUsize>("n")?.get()),
+      "delete" => match args.slot()? {
+        Some(name) => Command::DeleteSave(name),
+        None => return Err(args.missing("name"))
+      },
+      "quit" | "q" => Command::Quit,
+      "rq" => Command::RageQuit,
+      "fq" => Command::ForceQuit,
+      "dbg" => Command::Debug,
+      "print" => Command::Print,
+      "clear" => Command::Clear,
+      "history" => Command::History(args.optional("n")?.unwrap_or(20)),
+      "solve" => Command::Solve,
+      "path" => Command::Path,
+      "pause" => Command::Pause,
+      "disasm" => Command::Disasm {
+        addr:args.optional("addr")?,
+        count:args.optional("count")?
+      },
+      "mem" => Command::Mem {
+        addr:args.required("addr")?,
+        count:args.optional("count")?.unwrap_or(1)
+      },
+      "reg" => {
+        let reg:u8 = args.required("n")?;
+        if reg >= 8 {
+          return Err(args.invalid("n", &reg.to_string()));
+        }
+        let val:Option<u16> = args.optional("value")?;
+        if let Some(val) = val.filter(|val| *val >= WORDSIZE) {
+          return Err(args.invalid("value", &val.to_string()));
+        }
+        Command::Reg { reg, val }
+      }
+      "poke" => {
+        let addr = args.required("addr")?;
+        let mut vals:Vec<u16> = vec![args.required("value")?];
+        while let Some(val) = args.optional("value")? {
+          vals.push(val);
+        }
+        if let Some(val) = vals.iter().find(|val| **val >= WORDSIZE + 8) {
+          return Err(args.invalid("value", &val.to_string()));
+        }
+        Command::Poke { addr, vals }
+      }
+      "patches" => Command::Patches,
+      "break" => Command::Break(args.required("addr")?),
+      "unbreak" => Command::Unbreak(args.required("addr")?),
+      "stepback" => Command::StepBack(args.optional("n")?.unwrap_or(1)),
+      "reverse-continue" => Command::ReverseContinue,
+      "watch" => {
+        let rest = args.rest();
+        Command::Watch(Watchpoint::parse(&rest)?)
+      }
+      "unwatch" => Command::Unwatch(args.required("index")?),
+      "watches" => Command::Watches,
+      "trace" => Command::Trace(match args.tokens.next() {
+        None => TraceSetting::Show,
+        Some("clear") => TraceSetting::Clear,
+        Some("ops") => {
+          let mut ops = BTreeSet::new();
+          for name in args.rest() {
+            ops.insert(OpCode::from_name(name).ok_or_else(|| args.invalid("op", name))?);
+          }
+          TraceSetting::Ops(ops)
+        }
+        Some("pc") => match args.optional::<usize>("start")? {
+          Some(start) => {
+            let end:usize = args.required("end")?;
+            TraceSetting::Pcs(Some(start.min(end)..=start.max(end)))
+          }
+          None => TraceSetting::Pcs(None)
+        },
+        Some("depth") => TraceSetting::MaxDepth(args.optional("n")?),
+        Some("in") => TraceSetting::Function(args.optional("addr")?),
+        Some(filter) => return Err(args.invalid("ops | pc | depth | in | clear", filter))
+      }),
+      "profile" => Command::Profile(match args.tokens.next() {
+        None => ProfileSetting::Report,
+        Some("start") => ProfileSetting::Start,
+        Some("stop") => ProfileSetting::Stop,
+        Some("reset") => ProfileSetting::Reset,
+        Some(setting) => return Err(args.invalid("start | stop | reset", setting))
+      }),
+      "help" => Command::Help,
+      _ => return Err(VMErrors::UnknownCommand(args.name.to_string()))
+    };
+
+    args.finish()?;
+    Ok(cmd)
+  }
+}
+
+///Returns the `*help` listing.
+pub fn help() -> String {
+  let width = USAGE.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+  let mut s = String::from("System commands:\n");
+  for (usage, description) in USAGE {
+    writeln!(s, "  *{usage:<width$}  {description}").unwrap();
+  }
+  s
+}
+
+///The whitespace separated arguments of a command.
+struct Args<'a> {
+  name:&'a str,
+  tokens:SplitWhitespace<'a>
+}
+
+impl<'a> Args<'a> {
+  fn new(s:&'a str) -> Self {
+    let mut tokens = s.split_whitespace();
+    let name = tokens.next().unwrap_or_default();
+    Args { name, tokens }
+  }
+
+  fn invalid(&self, arg:&str, value:&str) -> VMErrors {
+    VMErrors::InvalidArgument {
+      command:self.name.to_string(),
+      arg:arg.to_string(),
+      value:value.to_string()
+    }
+  }
+
+  fn missing(&self, arg:&str) -> VMErrors {
+    VMErrors::MissingArgument {
+      command:self.name.to_string(),
+      arg:arg.to_string()
+    }
+  }
+
+  fn required<T:FromStr>(&mut self, arg:&str) -> Result<T, VMErrors> {
+    match self.optional(arg)? {
+      Some(val) => Ok(val),
+      None => Err(self.missing(arg))
+    }
+  }
+
+  fn optional<T:FromStr>(&mut self, arg:&str) -> Result<Option<T>, VMErrors> {
+    match self.tokens.next() {
+      Some(token) => token.parse::<T>().map(Some).map_err(|_| self.invalid(arg, token)),
+      None => Ok(None)
+    }
+  }
+
+  ///Parse an optional save slot name.
+  fn slot(&mut self) -> Result<Option<String>, VMErrors> {
+    match self.tokens.next() {
+      Some(name) if is_slot_name(name) => Ok(Some(name.to_string())),
+      Some(name) => Err(self.invalid("name", name)),
+      None => Ok(None)
+    }
+  }
+
+  fn rest(&mut self) -> Vec<&'a str> {
+    self.tokens.by_ref().collect()
+  }
+
+  ///Errors if there are arguments left over.
+  fn finish(mut self) -> Result<(), VMErrors> {
+    match self.tokens.next() {
+      Some(token) => Err(VMErrors::UnexpectedArgument {
+        command:self.name.to_string(),
+        value:token.to_string()
+      }),
+      None => Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{help, Command};
+  use crate::{errors::VMErrors, profile::ProfileSetting, trace::TraceSetting};
+
+  #[test]
+  fn parse() {
+    assert_eq!("*mem 1115 8\r\n".parse::<Command>().unwrap(), Command::Mem { addr:1115, count:8 });
+    assert_eq!("*reg 7 25734".parse::<Command>().unwrap(), Command::Reg { reg:7, val:Some(25734) });
+    assert_eq!("*save slot2\n".parse::<Command>().unwrap(), Command::Save(Some(String::from("slot2"))));
+    assert_eq!("*s".parse::<Command>().unwrap(), Command::Save(None));
+    assert_eq!("*delete before-vault".parse::<Command>().unwrap(), Command::DeleteSave(String::from("before-vault")));
+    assert_eq!("*disasm 10".parse::<Command>().unwrap(), Command::Disasm { addr:Some(10), count:None });
+    assert_eq!("*poke 5 1 2 3".parse::<Command>().unwrap(), Command::Poke { addr:5, vals:vec![1, 2, 3] });
+    assert_eq!("*undo".parse::<Command>().unwrap(), Command::Rewind(1));
+    assert_eq!("*trace pc 20 10".parse::<Command>().unwrap(), Command::Trace(TraceSetting::Pcs(Some(10..=20))));
+    assert_eq!("*trace depth".parse::<Command>().unwrap(), Command::Trace(TraceSetting::MaxDepth(None)));
+    assert_eq!("*history".parse::<Command>().unwrap(), Command::History(20));
+    assert_eq!("*profile".parse::<Command>().unwrap(), Command::Profile(ProfileSetting::Report));
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert!(matches!("*jump".parse::<Command>(), Err(VMErrors::UnknownCommand(cmd)) if cmd == "jump"));
+    assert!(matches!("*mem".parse::<Command>(), Err(VMErrors::MissingArgument { .. })));
+    assert!(matches!("*mem abc".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*reg 8".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*rewind 0".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*reg 7 32768".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*poke 5".parse::<Command>(), Err(VMErrors::MissingArgument { .. })));
+    assert!(matches!("*poke 5 40000".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*q now".parse::<Command>(), Err(VMErrors::UnexpectedArgument { .. })));
+    assert!(matches!("*save ../escape".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*delete".parse::<Command>(), Err(VMErrors::MissingArgument { .. })));
+    assert!(matches!("*trace ops call jump".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+    assert!(matches!("*trace pc 5".parse::<Command>(), Err(VMErrors::MissingArgument { .. })));
+    assert!(matches!("*profile pause".parse::<Command>(), Err(VMErrors::InvalidArgument { .. })));
+  }
+
+  #[test]
+  fn help_lists_commands() {
+    assert!(help().contains("*mem <addr> [count]"));
+  }
+}
@@ -0,0 +1,169 @@
+use crate::save::{SaveFormat, SAVE_DIR};
+use clap::ValueEnum;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+  env, fs,
+  path::{Path, PathBuf}
+};
+
+///Name of the config file looked for in the working directory or
+/// `SYNACOR_ROOT`.
+pub const CONFIG_FILE:&str = "vm.json";
+
+///The files a [`VM`](crate::vm::VM) reads and writes. The defaults are
+/// relative to the working directory. Paths read from a config file are
+/// relative to the file's directory so the [`VM`](crate::vm::VM) can be run
+/// from anywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmConfig {
+  ///The program binary a new game loads.
+  pub bin:PathBuf,
+  ///Directory the save slots are kept in.
+  pub save_dir:PathBuf,
+  ///Format save slots are written in.
+  pub save_format:SaveFormat,
+  ///Where executed instructions are logged while tracing is on.
+  pub trace:PathBuf,
+  ///Where `*mem` writes its listing.
+  pub console:PathBuf,
+  ///Where `*path` writes the path through the vault.
+  pub vault_path:PathBuf,
+  ///Where a core dump is written when the [`VM`](crate::vm::VM) faults.
+  /// [`None`] disables core dumps.
+  pub core:Option<PathBuf>
+}
+
+impl Default for VmConfig {
+  fn default() -> Self {
+    VmConfig {
+      bin:PathBuf::from("challenge.bin"),
+      save_dir:PathBuf::from(SAVE_DIR),
+      save_format:SaveFormat::default(),
+      trace:PathBuf::from("debug_log.txt"),
+      console:PathBuf::from("dbg_console.txt"),
+      vault_path:PathBuf::from("path.txt"),
+      core:Some(PathBuf::from("core.json"))
+    }
+  }
+}
+
+impl VmConfig {
+  ///Join every relative path onto `dir`.
+  pub fn relative_to(mut self, dir:&Path) -> Self {
+    let resolve = |path:&mut PathBuf| {
+      if path.is_relative() {
+        *path = dir.join(&*path);
+      }
+    };
+    resolve(&mut self.bin);
+    resolve(&mut self.save_dir);
+    resolve(&mut self.trace);
+    resolve(&mut self.console);
+    resolve(&mut self.vault_path);
+    if let Some(core) = &mut self.core {
+      resolve(core);
+    }
+    self
+  }
+
+  ///Read a JSON config file. Missing paths take their default and relative
+  /// paths are resolved against the file's directory.
+  pub fn read<P:AsRef<Path>>(path:P) -> Result<Self> {
+    let path = path.as_ref();
+    let config = serde_json::from_str::<VmConfig>(&fs::read_to_string(path)?)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok(config.relative_to(dir))
+  }
+
+  ///Override paths with the environment variables returned by `var`.
+  /// `SYNACOR_CORE` set to an empty string disables core dumps.
+  /// `SYNACOR_SAVE_FORMAT` holds `json` or `binary`, other values are
+  /// ignored.
+  pub fn with_env(mut self, var:impl Fn(&str) -> Option<String>) -> Self {
+    let paths = [
+      ("SYNACOR_BIN", &mut self.bin),
+      ("SYNACOR_SAVE_DIR", &mut self.save_dir),
+      ("SYNACOR_TRACE", &mut self.trace),
+      ("SYNACOR_CONSOLE", &mut self.console),
+      ("SYNACOR_VAULT_PATH", &mut self.vault_path)
+    ];
+    for (name, path) in paths {
+      if let Some(value) = var(name) {
+        *path = PathBuf::from(value);
+      }
+    }
+    if let Some(format) = var("SYNACOR_SAVE_FORMAT").and_then(|format| SaveFormat::from_str(&format, true).ok()) {
+      self.save_format = format;
+    }
+    if let Some(core) = var("SYNACOR_CORE") {
+      self.core = Some(PathBuf::from(core)).filter(|core| !core.as_os_str().is_empty());
+    }
+    self
+  }
+
+  ///Build the config from the environment. Reads the config file named by
+  /// `SYNACOR_CONFIG`, otherwise [`CONFIG_FILE`] in `SYNACOR_ROOT` or the
+  /// working directory if it exists. Without a config file the default paths
+  /// are relative to `SYNACOR_ROOT` if it is set. The other `SYNACOR_`
+  /// variables then override single paths.
+  pub fn load(file:Option<&Path>) -> Result<Self> {
+    let var = |name:&str| env::var(name).ok();
+    let root = var("SYNACOR_ROOT").map(PathBuf::from);
+    let file = file.map(Path::to_path_buf).or_else(|| var("SYNACOR_CONFIG").map(PathBuf::from));
+
+    let config = match (file, root) {
+      (Some(file), _) => VmConfig::read(file)?,
+      (None, root) => {
+        let root = root.unwrap_or_default();
+        match root.join(CONFIG_FILE) {
+          file if file.is_file() => VmConfig::read(file)?,
+          _ => VmConfig::default().relative_to(&root)
+        }
+      }
+    };
+    Ok(config.with_env(var))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::VmConfig;
+  use std::{
+    fs,
+    path::{Path, PathBuf}
+  };
+
+  #[test]
+  fn read() {
+    let dir = std::env::temp_dir().join("vm_config_test");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("vm.json");
+    fs::write(&path, r#"{"bin":"bin/challenge.bin","trace":"/var/log/trace.txt","core":null}"#).unwrap();
+
+    let config = VmConfig::read(&path).unwrap();
+    assert_eq!(config.bin, dir.join("bin/challenge.bin"));
+    assert_eq!(config.trace, Path::new("/var/log/trace.txt"));
+    assert_eq!(config.save_dir, dir.join("saves"));
+    assert_eq!(config.core, None);
+
+    //The default core dump is written next to the config file
+    fs::write(&path, "{}").unwrap();
+    let config = VmConfig::read(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(config.core, Some(dir.join("core.json")));
+  }
+
+  #[test]
+  fn env() {
+    let config = VmConfig::default().with_env(|name| match name {
+      "SYNACOR_SAVE_DIR" => Some(String::from("/tmp/saves")),
+      "SYNACOR_CORE" => Some(String::new()),
+      _ => None
+    });
+    assert_eq!(config.save_dir, PathBuf::from("/tmp/saves"));
+    assert_eq!(config.bin, PathBuf::from("challenge.bin"));
+    assert_eq!(config.core, None);
+  }
+}
@@ -0,0 +1,131 @@
+use crate::{
+  errors::Fault,
+  history::{Executed, History, HISTORY_LEN},
+  vm::VM
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs::{self, File},
+  io::BufWriter,
+  path::Path
+};
+
+///Everything known about a [`VM`] when it faulted. Written by [`VM::run`] and
+/// [`VM::dbg_run`] and loaded for post-mortem debugging with
+/// [`CoreDump::post_mortem`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoreDump {
+  pub fault:Fault,
+  ///The last executed instructions and their effects, oldest first. Ends
+  /// with the faulting instruction if it decoded.
+  pub history:Vec<Executed>,
+  pub vm:VM
+}
+
+///Borrowed form of [`CoreDump`] so a dump can be written without cloning the
+/// [`VM`].
+#[derive(Serialize)]
+struct CoreDumpRef<'a> {
+  fault:&'a Fault,
+  history:&'a [Executed],
+  vm:&'a VM
+}
+
+impl CoreDump {
+  ///Read a core dump written by [`VM::dump_core`].
+  pub fn read<P:AsRef<Path>>(path:P) -> Result<CoreDump> {
+    let dump = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&dump)?)
+  }
+
+  ///Print the fault report, then open the debugger prompt on the dumped
+  /// [`VM`]. The dumped history can be listed with `*history`. Execution
+  /// cannot be resumed so the prompt reopens until it is quit.
+  pub fn post_mortem(self) {
+    let CoreDump { fault, history, mut vm } = self;
+
+    vm.history = History::from(history);
+    let report = vm.fault_report(&fault);
+    vm.output.write_str(&format!("{report}\n"));
+
+    vm.running = true;
+    while vm.running {
+      vm.debug_prompt();
+      if vm.running {
+        vm.output.write_str("The VM faulted and cannot resume. Use `quit` to exit.\n");
+      }
+    }
+  }
+}
+
+impl VM {
+  ///Write the [`VM`]'s state, its recent instructions and a [`Fault`] to the
+  /// core file. Does nothing if core dumps are disabled.
+  pub fn dump_core(&mut self, fault:&Fault) {
+    let Some(path) = self.config.core.clone()
+    else {
+      return;
+    };
+
+    let msg = match self.write_core(&path, fault) {
+      Ok(()) => format!("Core dumped to {}\n", path.display()),
+      Err(err) => format!("Failed to write core dump to {}: {err}\n", path.display())
+    };
+    self.output.write_str(&msg);
+  }
+
+  ///Write a [`CoreDump`] of the [`VM`] to `path`.
+  pub fn write_core<P:AsRef<Path>>(&self, path:P, fault:&Fault) -> Result<()> {
+    let history = self.history.last(HISTORY_LEN);
+    let dump = CoreDumpRef { fault, history:&history, vm:self };
+    let file = File::create(path)?;
+    serde_json::to_writer(BufWriter::new(file), &dump)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::CoreDump;
+  use crate::{
+    errors::VMErrors,
+    io::{BufferInput, BufferOutput},
+    vm::{OpCode, VM}
+  };
+  use std::fs;
+
+  #[test]
+  fn dump_and_load() {
+    let path = std::env::temp_dir().join("vm_core_test.json");
+
+    //Push 5, Set R0 3, Ret
+    let mut vm = VM::new();
+    vm.mem = vec![2, 5, 1, 32768, 3, 18];
+    vm.config.core = Some(path.clone());
+    vm.set_output(BufferOutput::new());
+    let fault = vm.run().unwrap_err();
+
+    let dump = CoreDump::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(dump.fault, *fault);
+    assert_eq!(dump.fault.kind, VMErrors::EmptyStack);
+    assert_eq!(
+      dump.history.iter().map(|executed| executed.inst.op).collect::<Vec<OpCode>>(),
+      [OpCode::Push, OpCode::Set, OpCode::Ret, OpCode::Ret]
+    );
+    assert_eq!(dump.vm.reg[0], 3);
+    assert_eq!(dump.vm.pc, 5);
+
+    let mut dump = dump;
+    let out = BufferOutput::new();
+    dump.vm.set_input(BufferInput::new(["reg 0", "*history 1", "continue", "quit"]));
+    dump.vm.set_output(out.clone());
+    dump.post_mortem();
+
+    let out = out.contents();
+    assert!(out.starts_with("Fault at pc 5 executing `Ret`"));
+    assert!(out.contains("\tLast 4 instructions:\n\t    0: Push 5  ; push 5\n\t    2: Set r0 3  ; r0 = 3\n\t    5: Ret  ; pop 5\n\t    5: Ret\n"));
+    assert!(out.contains("Stopped at 5: Ret\n(dbg) r0: 3\n(dbg) Last 1 instructions:\n    5: Ret\n(dbg) The VM faulted and cannot resume."));
+  }
+}
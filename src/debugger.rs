@@ -0,0 +1,422 @@
+use crate::{
+  disassembler::disassemble,
+  errors::VMErrors,
+  instruction::Instruction,
+  patch::Patch,
+  vm::{VM, WORDSIZE}
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::BTreeSet,
+  fmt::{self, Write}
+};
+
+const HELP:&str = "Commands:
+  regs                  Print the registers
+  reg <n> [value]       Print or set register n
+  stack                 Print the stack, top last
+  mem <addr> [count]    Print memory words
+  disasm [addr] [count] Disassemble memory, defaults to the current pc
+  step [n]              Execute n instructions, defaults to 1
+  continue              Run until the next breakpoint
+  break <addr>          Add a breakpoint
+  delete <addr>         Remove a breakpoint
+  breakpoints           List the breakpoints
+  watchpoints           List the watchpoints
+  quit                  Halt the VM
+Lines starting with `*` are run as system commands.
+";
+
+///A register or memory address an instruction accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Location {
+  Register(u8),
+  Memory(u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+  Read,
+  Write
+}
+
+///What happens when a [`Watchpoint`] is triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchAction {
+  ///Stop at the debugger prompt after the instruction finishes. Under
+  /// [`VM::run`] this starts the debugger.
+  Break,
+  ///Print the access and keep running.
+  Log
+}
+
+///Watches a register or a range of memory for reads and writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watchpoint {
+  pub target:WatchTarget,
+  pub read:bool,
+  pub write:bool,
+  pub action:WatchAction
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchTarget {
+  Register(u8),
+  ///An inclusive range of memory addresses.
+  Memory(u16, u16)
+}
+
+impl Watchpoint {
+  ///Parse a [`Watchpoint`] from the arguments
+  /// `<reg N | mem ADDR [END]> [read | write | rw] [break | log]`. Watches
+  /// both reads and writes and breaks by default.
+  pub fn parse(args:&[&str]) -> Result<Watchpoint, VMErrors> {
+    let invalid = |arg:&str, value:Option<&&str>| VMErrors::InvalidArgument {
+      command:String::from("watch"),
+      arg:arg.to_string(),
+      value:value.map(|value| value.to_string()).unwrap_or_default()
+    };
+    let missing = |arg:&str| VMErrors::MissingArgument {
+      command:String::from("watch"),
+      arg:arg.to_string()
+    };
+
+    let mut args = args.iter().peekable();
+    let target = match args.next() {
+      Some(&"reg") => match args.next() {
+        Some(reg) => match reg.parse::<u8>() {
+          Ok(reg) if reg < 8 => WatchTarget::Register(reg),
+          _ => return Err(invalid("N", Some(reg)))
+        },
+        None => return Err(missing("N"))
+      },
+      Some(&"mem") => {
+        let start = args.next().ok_or_else(|| missing("ADDR"))?;
+        let start = start.parse::<u16>().map_err(|_| invalid("ADDR", Some(start)))?;
+        let end = match args.peek().and_then(|arg| arg.parse::<u16>().ok()) {
+          Some(end) => {
+            args.next();
+            end
+          }
+          None => start
+        };
+        WatchTarget::Memory(start.min(end), start.max(end))
+      }
+      Some(target) => return Err(invalid("reg | mem", Some(target))),
+      None => return Err(missing("reg | mem"))
+    };
+
+    let mut watchpoint = Watchpoint {
+      target,
+      read:true,
+      write:true,
+      action:WatchAction::Break
+    };
+    for arg in args {
+      match *arg {
+        "read" => (watchpoint.read, watchpoint.write) = (true, false),
+        "write" => (watchpoint.read, watchpoint.write) = (false, true),
+        "rw" => (watchpoint.read, watchpoint.write) = (true, true),
+        "break" => watchpoint.action = WatchAction::Break,
+        "log" => watchpoint.action = WatchAction::Log,
+        _ => return Err(invalid("read | write | rw | break | log", Some(arg)))
+      }
+    }
+    Ok(watchpoint)
+  }
+
+  fn matches(&self, location:Location, access:Access) -> bool {
+    let access = match access {
+      Access::Read => self.read,
+      Access::Write => self.write
+    };
+
+    access
+      && match (self.target, location) {
+        (WatchTarget::Register(watched), Location::Register(reg)) => watched == reg,
+        (WatchTarget::Memory(start, end), Location::Memory(addr)) => start <= addr && addr <= end,
+        _ => false
+      }
+  }
+}
+
+impl fmt::Display for Watchpoint {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.target {
+      WatchTarget::Register(reg) => write!(f, "reg {reg}")?,
+      WatchTarget::Memory(start, end) if start == end => write!(f, "mem {start}")?,
+      WatchTarget::Memory(start, end) => write!(f, "mem {start} {end}")?
+    }
+    let access = match (self.read, self.write) {
+      (true, true) => "rw",
+      (true, false) => "read",
+      _ => "write"
+    };
+    let action = match self.action {
+      WatchAction::Break => "break",
+      WatchAction::Log => "log"
+    };
+    write!(f, " {access} {action}")
+  }
+}
+
+///A triggered [`Watchpoint`].
+#[derive(Debug, Clone, Copy)]
+struct WatchHit {
+  location:Location,
+  access:Access,
+  value:u16,
+  action:WatchAction
+}
+
+///Breakpoint and watchpoint state for [`VM::dbg_run`].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Debugger {
+  ///Addresses [`VM::dbg_run`] stops at before executing.
+  pub breakpoints:BTreeSet<usize>,
+  pub watchpoints:Vec<Watchpoint>,
+  ///Number of instructions left to execute before stopping.
+  steps:Option<usize>,
+  ///Watchpoints triggered by the instruction being executed.
+  #[serde(skip)]
+  hits:Vec<WatchHit>
+}
+
+impl Debugger {
+  ///Stop before the next instruction.
+  pub fn pause(&mut self) {
+    self.steps = Some(0);
+  }
+
+  ///Returns true if execution should stop before the next instruction.
+  pub fn paused(&self) -> bool {
+    self.steps == Some(0)
+  }
+
+  ///Record an access to a register or memory address if a [`Watchpoint`]
+  /// covers it.
+  pub fn watch(&mut self, location:Location, access:Access, value:u16) {
+    for watchpoint in &self.watchpoints {
+      if watchpoint.matches(location, access) {
+        let action = watchpoint.action;
+        self.hits.push(WatchHit { location, access, value, action });
+      }
+    }
+  }
+
+  ///Returns true if execution should stop before the instruction at `pc`.
+  pub fn should_break(&mut self, pc:usize) -> bool {
+    match self.steps {
+      Some(0) => true,
+      Some(n) => {
+        self.steps = Some(n - 1);
+        self.breakpoints.contains(&pc)
+      }
+      None => self.breakpoints.contains(&pc)
+    }
+  }
+}
+
+impl VM {
+  ///Handle the [`Watchpoint`]s an [`Instruction`] triggered. Logged accesses
+  /// are printed and breaking ones stop [`VM::dbg_run`] before the next
+  /// instruction.
+  pub(crate) fn report_watch_hits(&mut self, inst:&Instruction) {
+    for hit in std::mem::take(&mut self.debugger.hits) {
+      match hit.action {
+        WatchAction::Break => self.debugger.pause(),
+        WatchAction::Log => {}
+      }
+
+      let location = match hit.location {
+        Location::Register(reg) => format!("r{reg}"),
+        Location::Memory(addr) => format!("mem[{addr}]")
+      };
+      let access = match hit.access {
+        Access::Read => "read",
+        Access::Write => "write"
+      };
+      self.output.write_str(&format!("[watch] {}: {inst}: {access} {location} = {}\n", inst.pc, hit.value));
+    }
+  }
+
+  ///Prompt for debugger commands until one resumes execution. Commands are
+  /// read from the [`VM`]'s input and replies printed to its output.
+  pub fn debug_prompt(&mut self) {
+    self.debugger.steps = None;
+    self.flush_trace();
+    let listing = disassemble(&self.mem, self.pc, 1);
+    self.output.write_str(&format!("Stopped at {}", listing.trim_start()));
+
+    while self.running {
+      self.output.write_str("(dbg) ");
+      let Some(line) = self.input.read_line()
+      else {
+        self.running = false;
+        break;
+      };
+
+      //System commands work from the prompt too
+      if line.starts_with('*') {
+        self.exe_system_commands(line);
+        continue;
+      }
+
+      let args = line.split_whitespace().collect::<Vec<&str>>();
+      let nums = args.iter().skip(1).map(|arg| arg.parse::<usize>()).collect::<Result<Vec<usize>, _>>();
+      let Ok(nums) = nums
+      else {
+        self.output.write_str("Arguments must be numbers\n");
+        continue;
+      };
+
+      let reply = match (args.first().copied().unwrap_or("step"), nums.as_slice()) {
+        //The instruction at the current pc runs without checking for a break
+        ("step" | "s", []) => {
+          self.debugger.steps = Some(0);
+          return;
+        }
+        ("step" | "s", [n]) => {
+          self.debugger.steps = Some(n.saturating_sub(1));
+          return;
+        }
+        ("continue" | "c", []) => return,
+        ("quit" | "q", []) => {
+          self.running = false;
+          break;
+        }
+        ("regs" | "r", []) => self.fmt_registers(),
+        ("reg", [n]) if *n < 8 => format!("r{n}: {}\n", self.reg[*n]),
+        ("reg", [n, val]) if *n < 8 && *val < WORDSIZE as usize => {
+          //Registers always exist so the patch cannot fail
+          self.poke(Patch::Register { reg:*n as u8, val:*val as u16 }).unwrap();
+          format!("r{n}: {}\n", self.reg[*n])
+        }
+        ("stack", []) => format!("{:?}\n", self.stack),
+        ("mem", [addr]) => self.fmt_memory(*addr, 1),
+        ("mem", [addr, count]) => self.fmt_memory(*addr, *count),
+        ("disasm", []) => disassemble(&self.mem, self.pc, 10),
+        ("disasm", [addr]) => disassemble(&self.mem, *addr, 10),
+        ("disasm", [addr, count]) => disassemble(&self.mem, *addr, *count),
+        ("break" | "b", [addr]) => {
+          self.debugger.breakpoints.insert(*addr);
+          format!("Breakpoint at {addr}\n")
+        }
+        ("delete" | "d", [addr]) => match self.debugger.breakpoints.remove(addr) {
+          true => format!("Removed breakpoint at {addr}\n"),
+          false => format!("No breakpoint at {addr}\n")
+        },
+        ("breakpoints", []) => format!("{:?}\n", self.debugger.breakpoints),
+        ("watchpoints", []) => self.fmt_watchpoints(),
+        ("help", []) => String::from(HELP),
+        _ => format!("Unknown debugger command `{}`. Type `help` for a list of commands.\n", line.trim())
+      };
+      self.output.write_str(&reply);
+    }
+  }
+
+  pub(crate) fn fmt_watchpoints(&self) -> String {
+    let mut s = String::new();
+    for (i, watchpoint) in self.debugger.watchpoints.iter().enumerate() {
+      writeln!(s, "{i}: {watchpoint}").unwrap();
+    }
+    s
+  }
+
+  fn fmt_registers(&self) -> String {
+    let mut s = String::new();
+    for (i, val) in self.reg.iter().enumerate() {
+      write!(s, "r{i}: {val} ").unwrap();
+    }
+    s.pop();
+    s + "\n"
+  }
+
+  fn fmt_memory(&self, addr:usize, count:usize) -> String {
+    if addr >= self.mem.len() {
+      return format!("{addr}: out of range\n");
+    }
+    let end = addr.saturating_add(count).min(self.mem.len());
+    format!("{addr}: {:?}\n", &self.mem[addr..end])
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Watchpoint;
+  use crate::{
+    assembler::assemble,
+    io::{BufferInput, BufferOutput},
+    vm::VM
+  };
+
+  #[test]
+  fn breakpoints() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nadd r0 r0 1\nout 'x'\nhalt").unwrap();
+    vm.debugger.breakpoints.insert(3);
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["reg 0", "step", "reg 0", "reg 7 25734", "continue"]));
+    vm.set_output(out.clone());
+    vm.dbg_run().unwrap();
+
+    let out = out.contents();
+    assert!(out.starts_with("Stopped at 3: Add r0 r0 1\n(dbg) r0: 1\n"));
+    assert!(out.contains("Stopped at 7: Out 'x'\n(dbg) r0: 2\n(dbg) r7: 25734\n(dbg) x"));
+    assert_eq!(vm.reg[7], 25734);
+    assert_eq!(vm.patches.len(), 1);
+  }
+
+  #[test]
+  fn watchpoints() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r7 5\nadd r0 r7 1\nwmem 100 r0\nrmem r1 100\nhalt").unwrap();
+    vm.mem.resize(101, 0);
+    vm.debugger.watchpoints.push(Watchpoint::parse(&["reg", "7", "read", "log"]).unwrap());
+    vm.debugger.watchpoints.push(Watchpoint::parse(&["mem", "90", "110", "write"]).unwrap());
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["reg 1", "continue"]));
+    vm.set_output(out.clone());
+    vm.dbg_run().unwrap();
+
+    let out = out.contents();
+    assert!(out.starts_with("[watch] 3: Add r0 r7 1: read r7 = 5\n"));
+    assert!(out.contains("[watch] 7: Wmem 100 r0: write mem[100] = 6\nStopped at 10: Rmem r1 100\n(dbg) r1: 0\n"));
+    assert_eq!(vm.reg[1], 6);
+  }
+
+  #[test]
+  fn run_watchpoints() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nwmem 100 r0\nout 'x'\nhalt").unwrap();
+    vm.mem.resize(101, 0);
+    vm.debugger.watchpoints.push(Watchpoint::parse(&["mem", "100", "write"]).unwrap());
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["mem 100", "continue"]));
+    vm.set_output(out.clone());
+    vm.run().unwrap();
+
+    assert_eq!(out.contents(), "[watch] 3: Wmem 100 r0: write mem[100] = 1\nStopped at 6: Out 'x'\n(dbg) 100: [1]\n(dbg) x");
+  }
+
+  #[test]
+  fn memory() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nhalt").unwrap();
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["mem 2 18446744073709551615", "mem 4", "quit"]));
+    vm.set_output(out.clone());
+    vm.debug_prompt();
+    assert!(out.contents().ends_with("(dbg) 2: [1, 0]\n(dbg) 4: out of range\n(dbg) "));
+  }
+
+  #[test]
+  fn parse_watchpoints() {
+    assert_eq!(Watchpoint::parse(&["mem", "20", "10", "read"]).unwrap().to_string(), "mem 10 20 read break");
+    assert!(Watchpoint::parse(&["reg", "8"]).is_err());
+    assert!(Watchpoint::parse(&["mem", "1", "sometimes"]).is_err());
+  }
+}
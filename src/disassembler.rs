@@ -0,0 +1,78 @@
+use crate::{
+  instruction::{decode, Instruction, Operand},
+  vm::OpCode
+};
+use std::fmt::Write;
+
+///Convert a little-endian binary into the words it encodes. A trailing odd
+/// byte is ignored.
+pub fn words(bin:&[u8]) -> Vec<u16> {
+  bin.chunks_exact(2).map(|word| u16::from_le_bytes([word[0], word[1]])).collect()
+}
+
+///Disassemble up to `count` instructions from `mem` starting at `addr`.
+/// Returns one line per instruction holding its address, [`OpCode`] and
+/// [`Operand`]s. Words which do not decode are listed as `.data`.
+pub fn disassemble(mem:&[u16], addr:usize, count:usize) -> String {
+  let mut out = String::new();
+  let mut pc = addr;
+
+  for _ in 0..count {
+    if pc >= mem.len() {
+      break;
+    }
+
+    match decode(mem, pc) {
+      Ok(inst) => {
+        writeln!(out, "{pc:>5}: {}", format_instruction(&inst)).unwrap();
+        pc = inst.next_pc();
+      }
+      Err(_) => {
+        writeln!(out, "{pc:>5}: .data {}", mem[pc]).unwrap();
+        pc += 1;
+      }
+    }
+  }
+  out
+}
+
+///Disassemble up to `count` instructions from a little-endian binary such as
+/// `challenge.bin` starting at the word address `addr`.
+pub fn disassemble_bin(bin:&[u8], addr:usize, count:usize) -> String {
+  disassemble(&words(bin), addr, count)
+}
+
+///Format an [`Instruction`] with registers as `r0`..`r7` and the literal
+/// argument of [`OpCode::Out`] as a character.
+pub fn format_instruction(inst:&Instruction) -> String {
+  match (inst.op, inst.operands()) {
+    (OpCode::Out, [Operand::Literal(val)]) => match char::from_u32(*val as u32) {
+      Some(c) => format!("{:?} {c:?}", inst.op),
+      None => inst.to_string()
+    },
+    _ => inst.to_string()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{disassemble, disassemble_bin};
+
+  #[test]
+  fn listing() {
+    //Set R7 25734, Out 'h', Out '\n', Out R0, Jmp 0, 32776
+    let mem = [1, 32775, 25734, 19, 104, 19, 10, 19, 32768, 6, 0, 32776];
+    let listing = disassemble(&mem, 0, 10);
+    assert_eq!(listing, "    0: Set r7 25734\n    3: Out 'h'\n    5: Out '\\n'\n    7: Out r0\n    9: Jmp 0\n   11: .data 32776\n");
+
+    //Only the requested number of instructions are listed
+    assert_eq!(disassemble(&mem, 3, 1), "    3: Out 'h'\n");
+  }
+
+  #[test]
+  fn listing_bin() {
+    //Noop, Halt
+    let bin = [21, 0, 0, 0];
+    assert_eq!(disassemble_bin(&bin, 0, 2), "    0: Noop\n    1: Halt\n");
+  }
+}
@@ -1,11 +1,88 @@
+use crate::instruction::Instruction;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum VMErrors<'a> {
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VMErrors {
   #[error("Unknown OPCODE `{0}`")]
   UnknownOpcode(u16),
+  #[error("Operand `{0}` is not a valid encoding. Values above 32775 are invalid.")]
+  InvalidOperand(u16),
+  #[error("Operand `{0}` must be a register.")]
+  InvalidRegister(u16),
+  #[error("Address `{0}` is outside of memory.")]
+  OutOfBounds(usize),
   #[error("Tried to remove a value from the stack when the stack was empty.")]
   EmptyStack,
-  #[error("Command '{0}' is not recognized")]
-  UnknownCommand(&'a str)
+  #[error("Tried to divide by zero.")]
+  DivisionByZero,
+  #[error("Value `{0}` is not an ASCII character and cannot be printed.")]
+  InvalidCharacter(u16),
+  #[error("Tried to read input after the input was exhausted.")]
+  InputEof,
+  #[error("Stopped after reaching the limit of {0} instructions.")]
+  CycleLimit(u64),
+  #[error("The save has no version header. It was written by an older version of the VM and cannot be loaded.")]
+  UnversionedSave,
+  #[error("Save format version {version} is not supported. Expected version {expected}.")]
+  UnsupportedSave { version:u32, expected:u32 },
+  #[error("There is no save named `{0}`.")]
+  MissingSave(String),
+  #[error("Cannot rewind {requested} commands. Only {available} commands can be undone.")]
+  RewindTooFar { requested:usize, available:usize },
+  #[error("Invalid snapshot: {0}")]
+  InvalidSnapshot(String),
+  #[error("Replay output differs on line {line}. Expected `{expected}` but found `{found}`.")]
+  ReplayMismatch { line:usize, expected:String, found:String },
+  #[error("Command '{0}' cannot be replayed so it cannot be used while recording a transcript.")]
+  Unreplayable(String),
+  #[error("Script line {line}: {msg}")]
+  Script { line:usize, msg:String },
+  #[error("Script line {line}: expected the output to contain `{expected}`")]
+  ExpectFailed { line:usize, expected:String },
+  #[error("Assembly failed on line {line}: {msg}")]
+  Assembly { line:usize, msg:String },
+  #[error("Command '{0}' is not recognized. Type `*help` for a list of commands.")]
+  UnknownCommand(String),
+  #[error("Command '{command}' is missing the argument `{arg}`")]
+  MissingArgument { command:String, arg:String },
+  #[error("`{value}` is not a valid `{arg}` for command '{command}'")]
+  InvalidArgument { command:String, arg:String, value:String },
+  #[error("Command '{command}' does not take the argument `{value}`")]
+  UnexpectedArgument { command:String, value:String }
+}
+
+///Number of values from the top of the stack a [`Fault`] records.
+pub const FAULT_STACK_LEN:usize = 16;
+
+///Report of an error which stopped the [`VM`](crate::vm::VM). Records the
+/// state of the [`VM`](crate::vm::VM) when the error occurred.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fault {
+  pub kind:VMErrors,
+  ///Address of the instruction which faulted.
+  pub pc:usize,
+  ///The faulting instruction. [`None`] if it could not be decoded.
+  pub instruction:Option<Instruction>,
+  pub reg:[u16; 8],
+  pub stack_len:usize,
+  ///Up to [`FAULT_STACK_LEN`] values from the top of the stack. The top of
+  /// the stack is last.
+  pub stack_top:Vec<u16>
+}
+
+impl fmt::Display for Fault {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.instruction {
+      Some(inst) => writeln!(f, "Fault at pc {} executing `{inst}`: {}", self.pc, self.kind)?,
+      None => writeln!(f, "Fault at pc {}: {}", self.pc, self.kind)?
+    }
+
+    write!(f, "\tRegs:")?;
+    for (i, val) in self.reg.iter().enumerate() {
+      write!(f, " r{i}: {val}")?;
+    }
+    write!(f, "\n\tStack (Len: {}, top last): {:?}", self.stack_len, self.stack_top)
+  }
 }
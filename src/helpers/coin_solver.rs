@@ -2,6 +2,23 @@ use crate::vm::VM;
 use itertools::Itertools;
 use std::collections::VecDeque;
 
+///The coins and their values, given by the number of sides on each coin's
+/// shape.
+const COINS:[(&str, u16); 5] = [("red coin", 2), ("corroded coin", 3), ("shiny coin", 5), ("concave coin", 7), ("blue coin", 9)];
+
+///Returns the order the coins must be placed in to solve the equation
+/// `_ + _ * _^2 + _^3 - _ = 399` on the monument.
+pub fn coin_order() -> Option<Vec<&'static str>> {
+  COINS
+    .into_iter()
+    .permutations(5)
+    .find(|coins| {
+      let [a, b, c, d, e] = [0, 1, 2, 3, 4].map(|i| coins[i].1 as i32);
+      a + b * c.pow(2) + d.pow(3) - e == 399
+    })
+    .map(|coins| coins.into_iter().map(|(coin, _)| coin).collect())
+}
+
 pub fn solver(vm:&mut VM) {
   let coins = Vec::from(["red coin", "blue coin", "shiny coin", "concave coin", "corroded coin"]);
   let pairs = coins.into_iter().permutations(5).collect::<Vec<Vec<&str>>>();
@@ -13,10 +30,10 @@ pub fn solver(vm:&mut VM) {
 fn place_coins(vm:&mut VM, pairs:Vec<Vec<&str>>) {
   for coins in pairs {
     //Place the coins in the pair
-    for coin in 0..5 {
-      let cmd = String::from("use ") + coins[coin] + "\n";
+    for (i, coin) in coins.iter().enumerate() {
+      let cmd = String::from("use ") + coin + "\n";
       vm.push_input(cmd);
-      if coin == 4 {
+      if i == 4 {
         pick_up(vm)
       }
     }
@@ -25,15 +42,15 @@ fn place_coins(vm:&mut VM, pairs:Vec<Vec<&str>>) {
 
 fn pick_up(vm:&mut VM) {
   let coins = VecDeque::from(["red coin", "blue coin", "shiny coin", "concave coin", "corroded coin"]);
-  for coin in 0..5 {
-    let cmd = String::from("take ") + coins[coin] + "\n";
+  for coin in coins {
+    let cmd = String::from("take ") + coin + "\n";
     vm.push_input(cmd);
   }
 }
 
 #[cfg(test)]
 mod test {
-  use super::solver;
+  use super::{coin_order, solver};
   use crate::vm::VM;
 
   #[test]
@@ -41,4 +58,9 @@ mod test {
     let mut vm = VM::new();
     solver(&mut vm)
   }
+
+  #[test]
+  fn order() {
+    assert_eq!(coin_order().unwrap(), ["blue coin", "red coin", "shiny coin", "concave coin", "corroded coin"]);
+  }
 }
@@ -2,4 +2,8 @@ mod coin_solver;
 mod pathing;
 mod teleporter;
 
-pub use self::{coin_solver::solver, pathing::*, teleporter::*};
+pub use self::{
+  coin_solver::{coin_order, solver},
+  pathing::*,
+  teleporter::*
+};
@@ -1,9 +1,5 @@
 use crate::vm::WORDSIZE;
-use std::{
-  collections::{HashSet, VecDeque},
-  fs,
-  io::Write
-};
+use std::collections::{HashSet, VecDeque};
 
 #[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Operation {
@@ -37,6 +33,23 @@ pub struct Graph {
   nodes:VecDeque<Node> // adj_list:Vec<Vertex>
 }
 
+///Builds the graph of the vault's grid. Cells are numbered row by row
+/// skipping the operator tiles, starting from the orb's pedestal at cell 6.
+pub fn vault() -> Graph {
+  use Operation::{Add, Mul, Sub};
+
+  let mut graph = Graph::new(8);
+  graph.add_node(0, 8, vec![(Sub, 1), (Sub, 3), (Mul, 3), (Mul, 4), (Mul, 2)]);
+  graph.add_node(1, 1, vec![(Mul, 5), (Mul, 3), (Sub, 0), (Sub, 3)]);
+  graph.add_node(2, 4, vec![(Mul, 0), (Mul, 3), (Mul, 4), (Add, 4)]);
+  graph.add_node(3, 11, vec![(Sub, 0), (Sub, 1), (Mul, 0), (Mul, 1), (Mul, 4), (Mul, 5), (Sub, 7), (Mul, 2), (Sub, 4), (Sub, 5)]);
+  graph.add_node(4, 4, vec![(Mul, 2), (Mul, 0), (Mul, 3), (Add, 2), (Sub, 3), (Sub, 5), (Sub, 7)]);
+  graph.add_node(5, 18, vec![(Sub, 7), (Sub, 4), (Sub, 3), (Mul, 3), (Mul, 1), (Mul, 7)]);
+  graph.add_node(6, 22, vec![(Sub, 7), (Sub, 4), (Add, 4), (Add, 2)]);
+  graph.add_node(7, 9, vec![(Sub, 4), (Sub, 3), (Sub, 5), (Mul, 5)]);
+  graph
+}
+
 impl Graph {
   pub fn new(cap:usize) -> Self {
     let mut nodes = VecDeque::new();
@@ -55,10 +68,7 @@ impl Graph {
   }
 
   ///Goal params take in tuples of (number, tile).
-  pub fn get_shortest_path(&mut self, start:(usize, u16), goal:(usize, u16)) {
-    //Create the file where the path will print
-    let mut file = fs::File::create("path.txt").unwrap();
-
+  pub fn get_shortest_path(&mut self, start:(usize, u16), goal:(usize, u16)) -> Option<Vec<String>> {
     //Push the start (cell, value) to the queue
     let start = (start.0, start.1, vec![]);
     let mut queue = VecDeque::new();
@@ -67,14 +77,13 @@ impl Graph {
     //Initialize the the HashSet of visited cells
     let mut visited = HashSet::new();
 
-    'path: while !queue.is_empty() {
+    while !queue.is_empty() {
       let (current_cell, current_val, path) = queue.pop_front().unwrap();
 
       //Break if a path has been found
       if (current_cell, current_val) == goal {
         //Return the path
-        write!(file, "{:?}", path).unwrap();
-        break 'path;
+        return Some(path);
       }
 
       //Explore all the current node's neighbors
@@ -98,6 +107,7 @@ impl Graph {
         }
       }
     }
+    None
   }
 
   ///Given the values of a start cell, a destination cell, and the edge between
@@ -124,25 +134,11 @@ impl Graph {
 
 #[cfg(test)]
 mod test {
-  use super::{
-    Graph,
-    Operation::{Add, Mul, Sub}
-  };
+  use super::vault;
 
   #[test]
   fn path() {
-    //Create the graph
-    let mut graph = Graph::new(8);
-    graph.add_node(0, 8, vec![(Sub, 1), (Sub, 3), (Mul, 3), (Mul, 4), (Mul, 2)]);
-    graph.add_node(1, 1, vec![(Mul, 5), (Mul, 3), (Sub, 0), (Sub, 3)]);
-    graph.add_node(2, 4, vec![(Mul, 0), (Mul, 3), (Mul, 4), (Add, 4)]);
-    graph.add_node(3, 11, vec![(Sub, 0), (Sub, 1), (Mul, 0), (Mul, 1), (Mul, 4), (Mul, 5), (Sub, 7), (Mul, 2), (Sub, 4), (Sub, 5)]);
-    graph.add_node(4, 4, vec![(Mul, 2), (Mul, 0), (Mul, 3), (Add, 2), (Sub, 3), (Sub, 5), (Sub, 7)]);
-    graph.add_node(5, 18, vec![(Sub, 7), (Sub, 4), (Sub, 3), (Mul, 3), (Mul, 1), (Mul, 7)]);
-    graph.add_node(6, 22, vec![(Sub, 7), (Sub, 4), (Add, 4), (Add, 2)]);
-    graph.add_node(7, 9, vec![(Sub, 4), (Sub, 3), (Sub, 5), (Mul, 5)]);
-
     //Calculate the path
-    graph.get_shortest_path((6, 22), (1, 30));
+    assert!(vault().get_shortest_path((6, 22), (1, 30)).is_some());
   }
 }
@@ -27,6 +27,12 @@ pub fn teleport_check(r0:u16, r1:u16, r7:u16, cache:&mut Cache) -> u16 {
   })
 }
 
+///Search for the value of R7 which makes the teleporter's confirmation check
+/// return 6, starting from `from`.
+pub fn find_r7(from:u16) -> Option<u16> {
+  (from..WORDSIZE).find(|&r7| teleport_check(4, 1, r7, &mut [[None; WORDSIZE as usize]; 5]) == 6)
+}
+
 #[cfg(test)]
 mod test {
   use super::teleport_check;
@@ -0,0 +1,242 @@
+use crate::{disassembler::format_instruction, errors::Fault, instruction::Instruction, journal::Effect, vm::VM};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+///Number of executed instructions the [`VM`] remembers.
+pub const HISTORY_LEN:usize = 4096;
+
+///Number of recently executed instructions a fault report lists.
+pub const FAULT_HISTORY_LEN:usize = 16;
+
+///Number of effects the [`History`] keeps. No instruction makes more than two
+/// so the effects of every remembered instruction fit.
+const EFFECTS_LEN:usize = 2 * HISTORY_LEN;
+
+///An executed [`Instruction`] and the changes it made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Executed {
+  pub inst:Instruction,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub effects:Vec<Effect>
+}
+
+impl fmt::Display for Executed {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:>5}: {}", self.inst.pc, format_instruction(&self.inst))?;
+    let mut sep = "  ;";
+    for effect in &self.effects {
+      match *effect {
+        Effect::Register(reg, _, val) => write!(f, "{sep} r{reg} = {val}")?,
+        Effect::Memory(addr, _, val) => write!(f, "{sep} mem[{addr}] = {val}")?,
+        Effect::Push(val) => write!(f, "{sep} push {val}")?,
+        Effect::Pop(val) => write!(f, "{sep} pop {val}")?,
+        Effect::Input(c, _) => write!(f, "{sep} read {:?}", c as char)?,
+        Effect::Halt => write!(f, "{sep} halt")?,
+        Effect::Enter | Effect::Leave(_) => continue
+      }
+      sep = ",";
+    }
+    Ok(())
+  }
+}
+
+///The last [`HISTORY_LEN`] instructions the [`VM`] executed and their
+/// effects. Always recorded. The buffers are rings which stop growing once
+/// full so recording does not allocate.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+  ///Ring of executed instructions and the number of effects recorded before
+  /// each. Instruction `n` is kept at `n % HISTORY_LEN`.
+  insts:Vec<(Instruction, u64)>,
+  ///Ring of effects. Effect `n` is kept at `n % EFFECTS_LEN`.
+  effects:Vec<Effect>,
+  ///Number given to the next executed instruction.
+  next:u64,
+  ///Number of effects ever recorded.
+  pushed:u64
+}
+
+impl History {
+  ///Returns the number of instructions remembered.
+  pub fn len(&self) -> usize {
+    (self.next - self.start()) as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  ///Returns the number of the oldest instruction remembered. Instructions
+  /// whose effects have been overwritten are forgotten.
+  fn start(&self) -> u64 {
+    let kept = self.pushed.saturating_sub(EFFECTS_LEN as u64);
+    (self.next.saturating_sub(HISTORY_LEN as u64)..self.next)
+      .find(|num| self.insts[*num as usize % HISTORY_LEN].1 >= kept)
+      .unwrap_or(self.next)
+  }
+
+  ///Remember an executed [`Instruction`], forgetting the oldest once
+  /// [`HISTORY_LEN`] are kept.
+  pub(crate) fn record(&mut self, inst:Instruction) {
+    let entry = (inst, self.pushed);
+    match self.insts.len() < HISTORY_LEN {
+      true => self.insts.push(entry),
+      false => self.insts[self.next as usize % HISTORY_LEN] = entry
+    }
+    self.next += 1;
+  }
+
+  ///Remember an effect of the last recorded instruction. If the oldest effect
+  /// is forgotten, so is the instruction it belongs to.
+  pub(crate) fn push(&mut self, effect:Effect) {
+    match self.effects.len() < EFFECTS_LEN {
+      true => self.effects.push(effect),
+      false => self.effects[self.pushed as usize % EFFECTS_LEN] = effect
+    }
+    self.pushed += 1;
+  }
+
+  ///Returns the effects of the last recorded instruction.
+  pub(crate) fn last_effects(&self) -> impl Iterator<Item = Effect> + '_ {
+    let first = self.next.checked_sub(1).map_or(self.pushed, |num| self.insts[num as usize % HISTORY_LEN].1);
+    (first..self.pushed).map(|i| self.effects[i as usize % EFFECTS_LEN])
+  }
+
+  ///Returns up to the last `n` executed instructions with their effects,
+  /// oldest first.
+  pub fn last(&self, n:usize) -> Vec<Executed> {
+    let start = self.start().max(self.next.saturating_sub(n as u64));
+    (start..self.next)
+      .map(|num| {
+        let (inst, first) = self.insts[num as usize % HISTORY_LEN];
+        let end = match num + 1 == self.next {
+          true => self.pushed,
+          false => self.insts[(num + 1) as usize % HISTORY_LEN].1
+        };
+        let effects = (first..end).map(|i| self.effects[i as usize % EFFECTS_LEN]).collect();
+        Executed { inst, effects }
+      })
+      .collect()
+  }
+}
+
+impl From<Vec<Executed>> for History {
+  fn from(executed:Vec<Executed>) -> Self {
+    let mut history = History::default();
+    for Executed { inst, effects } in executed {
+      history.record(inst);
+      effects.into_iter().for_each(|effect| history.push(effect));
+    }
+    history
+  }
+}
+
+impl VM {
+  ///Print up to the last `n` executed instructions and the changes they made.
+  pub(crate) fn print_history(&mut self, n:usize) {
+    let executed = self.history.last(n);
+    let mut s = match executed.len() {
+      0 => String::from("No instructions have been executed\n"),
+      len => format!("Last {len} instructions:\n")
+    };
+    for executed in &executed {
+      s += &format!("{executed}\n");
+    }
+    self.output.write_str(&s);
+  }
+
+  ///Returns the report of a [`Fault`] followed by the last
+  /// [`FAULT_HISTORY_LEN`] executed instructions.
+  pub fn fault_report(&self, fault:&Fault) -> String {
+    let executed = self.history.last(FAULT_HISTORY_LEN);
+    let mut s = fault.to_string();
+    if !executed.is_empty() {
+      s += &format!("\n\tLast {} instructions:", executed.len());
+      for executed in &executed {
+        s += &format!("\n\t{executed}");
+      }
+    }
+    s
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{History, FAULT_HISTORY_LEN, HISTORY_LEN};
+  use crate::{assembler::assemble, instruction::decode, io::BufferOutput, journal::Effect, vm::VM};
+
+  #[test]
+  fn history() {
+    let mut vm = VM::new();
+    vm.mem = assemble(
+      "
+            set r0 5
+            wmem 100 r0
+            call f
+            halt
+      f:    push r0
+            pop r1
+            ret
+      "
+    )
+    .unwrap();
+    vm.mem.resize(101, 0);
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    vm.run().unwrap();
+    vm.exe_system_commands(String::from("*history 4"));
+    vm.exe_system_commands(String::from("*history"));
+
+    let out = out.contents();
+    assert!(out.starts_with("Last 4 instructions:\n    9: Push r0  ; push 5\n   11: Pop r1  ; pop 5, r1 = 5\n   13: Ret  ; pop 8\n    8: Halt  ; halt\nLast 7"));
+    assert!(out.contains("    0: Set r0 5  ; r0 = 5\n    3: Wmem 100 r0  ; mem[100] = 5\n    6: Call 9  ; push 8\n"));
+  }
+
+  #[test]
+  fn commands_not_recorded() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 5\nhalt").unwrap();
+    vm.mem.resize(10, 0);
+    vm.set_output(BufferOutput::new());
+    vm.run().unwrap();
+    vm.exe_system_commands(String::from("*reg 7 25734"));
+    vm.exe_system_commands(String::from("*poke 4 1 2 3 4"));
+
+    let executed = vm.history.last(2);
+    assert_eq!(executed[0].effects, [Effect::Register(0, 0, 5)]);
+    assert_eq!(executed[1].effects, [Effect::Halt]);
+  }
+
+  #[test]
+  fn fault_report() {
+    //Set r0 1 twenty times, then Pop with an empty stack
+    let mut vm = VM::new();
+    vm.mem = [1, 32768, 1].repeat(20);
+    vm.mem.extend([3, 32768]);
+    vm.config.core = None;
+    vm.set_output(BufferOutput::new());
+    let fault = vm.run().unwrap_err();
+
+    let report = vm.fault_report(&fault);
+    assert!(report.starts_with(&format!("{fault}\n\tLast {FAULT_HISTORY_LEN} instructions:\n\t   15: Set r0 1  ; r0 = 1\n")));
+    assert!(report.ends_with("\n\t   57: Set r0 1  ; r0 = 1\n\t   60: Pop r0"));
+  }
+
+  #[test]
+  fn bounded() {
+    let inst = decode(&[1, 32768, 1], 0).unwrap();
+    let mut history = History::default();
+    for _ in 0..HISTORY_LEN + 10 {
+      history.record(inst);
+      history.push(Effect::Register(0, 0, 1));
+      history.push(Effect::Register(1, 0, 1));
+    }
+    assert_eq!(history.len(), HISTORY_LEN);
+    assert!(history.last(HISTORY_LEN).iter().all(|executed| executed.effects.len() == 2));
+
+    //A third effect forgets the oldest instruction so each kept one is whole
+    history.push(Effect::Halt);
+    assert_eq!(history.len(), HISTORY_LEN - 1);
+    assert_eq!(history.last(1)[0].effects.len(), 3);
+  }
+}
@@ -0,0 +1,155 @@
+use crate::{
+  errors::VMErrors,
+  vm::{OpCode, WORDSIZE}
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+///Number of registers in the architecture.
+pub const REGISTERS:u16 = 8;
+
+///A single argument of an [`Instruction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operand {
+  ///A value in the range 0..=32767.
+  Literal(u16),
+  ///One of the 8 registers, encoded as 32768..=32775.
+  Register(u8),
+  ///A value of 32776 or greater. These are not valid encodings.
+  Invalid(u16)
+}
+
+impl Operand {
+  ///Create a new [`Operand`] from its encoded value.
+  pub fn new(raw:u16) -> Self {
+    match raw {
+      raw if raw < WORDSIZE => Operand::Literal(raw),
+      raw if raw < WORDSIZE + REGISTERS => Operand::Register((raw - WORDSIZE) as u8),
+      raw => Operand::Invalid(raw)
+    }
+  }
+
+  ///Returns the encoded value of the [`Operand`].
+  pub fn raw(&self) -> u16 {
+    match *self {
+      Operand::Literal(val) => val,
+      Operand::Register(reg) => WORDSIZE + reg as u16,
+      Operand::Invalid(val) => val
+    }
+  }
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Operand::Literal(val) => write!(f, "{val}"),
+      Operand::Register(reg) => write!(f, "r{reg}"),
+      Operand::Invalid(val) => write!(f, "?{val}")
+    }
+  }
+}
+
+///An [`OpCode`] and its [`Operand`]s as decoded from memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instruction {
+  ///Address of the [`OpCode`].
+  pub pc:usize,
+  pub op:OpCode,
+  ///Operands of the instruction. Only the first [`OpCode::arity`] are used.
+  args:[Operand; 3]
+}
+
+impl Instruction {
+  ///Returns the [`Operand`]s of the [`Instruction`].
+  pub fn operands(&self) -> &[Operand] {
+    &self.args[..self.op.arity()]
+  }
+
+  ///Returns the number of words the [`Instruction`] occupies in memory.
+  pub fn size(&self) -> usize {
+    self.op.arity() + 1
+  }
+
+  ///Returns the address of the [`Instruction`] after this one.
+  pub fn next_pc(&self) -> usize {
+    self.pc + self.size()
+  }
+}
+
+impl fmt::Display for Instruction {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self.op)?;
+    for arg in self.operands() {
+      write!(f, " {arg}")?;
+    }
+    Ok(())
+  }
+}
+
+///Decode the [`Instruction`] at address `pc`. Errors if the [`OpCode`] is
+/// unknown, if the [`Instruction`] runs past the end of memory, or if an
+/// [`Operand`] is not a valid encoding.
+#[inline]
+pub fn decode(mem:&[u16], pc:usize) -> Result<Instruction, VMErrors> {
+  let Some(&code) = mem.get(pc)
+  else {
+    return Err(VMErrors::OutOfBounds(pc));
+  };
+  let op = OpCode::new(code)?;
+
+  let mut args = [Operand::Literal(0); 3];
+  for (i, arg) in args.iter_mut().enumerate().take(op.arity()) {
+    let addr = pc + 1 + i;
+    let Some(&raw) = mem.get(addr)
+    else {
+      return Err(VMErrors::OutOfBounds(addr));
+    };
+    *arg = match Operand::new(raw) {
+      Operand::Invalid(raw) => return Err(VMErrors::InvalidOperand(raw)),
+      operand => operand
+    };
+  }
+
+  Ok(Instruction { pc, op, args })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{decode, Operand};
+  use crate::vm::OpCode;
+
+  #[test]
+  fn operands() {
+    assert_eq!(Operand::new(32767), Operand::Literal(32767));
+    assert_eq!(Operand::new(32768), Operand::Register(0));
+    assert_eq!(Operand::new(32775), Operand::Register(7));
+    assert_eq!(Operand::new(32776), Operand::Invalid(32776));
+    assert_eq!(Operand::Register(7).raw(), 32775);
+  }
+
+  #[test]
+  fn decode_instructions() {
+    //Add R0 R1 4, Out 'a', Ret
+    let mem = [9, 32768, 32769, 4, 19, 97, 18];
+
+    let add = decode(&mem, 0).unwrap();
+    assert_eq!(add.op, OpCode::Add);
+    assert_eq!(add.operands(), &[Operand::Register(0), Operand::Register(1), Operand::Literal(4)]);
+    assert_eq!(add.next_pc(), 4);
+    assert_eq!(add.to_string(), "Add r0 r1 4");
+
+    let ret = decode(&mem, 6).unwrap();
+    assert_eq!(ret.op, OpCode::Ret);
+    assert!(ret.operands().is_empty());
+  }
+
+  #[test]
+  fn reject_invalid() {
+    //Set R0 32776
+    assert!(decode(&[1, 32768, 32776], 0).is_err());
+    //Unknown opcode
+    assert!(decode(&[22], 0).is_err());
+    //Truncated instruction
+    assert!(decode(&[1, 32768], 0).is_err());
+  }
+}
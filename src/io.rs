@@ -0,0 +1,299 @@
+use std::{
+  collections::VecDeque,
+  fmt::Debug,
+  fs::File,
+  io::{self, stdin, BufRead, BufReader, LineWriter, Write},
+  path::Path,
+  sync::{
+    mpsc::{Receiver, Sender},
+    Arc, Mutex
+  }
+};
+
+///A source of text for the [`VM`](crate::vm::VM)'s `In` opcode.
+pub trait Input: Debug {
+  ///Returns the next line of input including its linebreak. Returns [`None`]
+  /// once the source is exhausted.
+  fn read_line(&mut self) -> Option<String>;
+}
+
+///A destination for the characters the [`VM`](crate::vm::VM)'s `Out` opcode
+/// prints.
+pub trait Output: Debug {
+  fn write_char(&mut self, c:char);
+
+  fn write_str(&mut self, s:&str) {
+    for c in s.chars() {
+      self.write_char(c);
+    }
+  }
+
+  ///Returns the error which stopped the output, if any. Outputs which cannot
+  /// fail always return [`None`].
+  fn error(&self) -> Option<&io::Error> {
+    None
+  }
+}
+
+pub fn default_input() -> Box<dyn Input> {
+  Box::new(StdinInput)
+}
+
+pub fn default_output() -> Box<dyn Output> {
+  Box::new(StdoutOutput)
+}
+
+///Reads lines from the process's standard input.
+#[derive(Debug, Default)]
+pub struct StdinInput;
+
+impl Input for StdinInput {
+  fn read_line(&mut self) -> Option<String> {
+    let mut s = String::new();
+    match stdin().read_line(&mut s) {
+      Ok(0) | Err(_) => None,
+      Ok(_) => Some(s)
+    }
+  }
+}
+
+///Prints to the process's standard output.
+#[derive(Debug, Default)]
+pub struct StdoutOutput;
+
+impl Output for StdoutOutput {
+  fn write_char(&mut self, c:char) {
+    print!("{c}");
+  }
+
+  fn write_str(&mut self, s:&str) {
+    print!("{s}");
+  }
+}
+
+///Reads lines from an in-memory queue.
+#[derive(Debug, Default)]
+pub struct BufferInput {
+  lines:VecDeque<String>
+}
+
+impl BufferInput {
+  ///Create a new [`BufferInput`]. A linebreak is appended to any line missing
+  /// one.
+  pub fn new<S:Into<String>>(lines:impl IntoIterator<Item = S>) -> Self {
+    let mut input = BufferInput::default();
+    for line in lines {
+      input.push_line(line);
+    }
+    input
+  }
+
+  pub fn push_line<S:Into<String>>(&mut self, line:S) {
+    let mut line = line.into();
+    if !line.ends_with('\n') {
+      line.push('\n');
+    }
+    self.lines.push_back(line);
+  }
+}
+
+impl Input for BufferInput {
+  fn read_line(&mut self) -> Option<String> {
+    self.lines.pop_front()
+  }
+}
+
+///Collects output in memory. Clones share the same buffer so a handle can be
+/// kept after the [`BufferOutput`] is given to the [`VM`](crate::vm::VM).
+#[derive(Debug, Default, Clone)]
+pub struct BufferOutput {
+  buf:Arc<Mutex<String>>
+}
+
+impl BufferOutput {
+  pub fn new() -> Self {
+    BufferOutput::default()
+  }
+
+  ///Returns a copy of everything written so far.
+  pub fn contents(&self) -> String {
+    self.buf.lock().unwrap().clone()
+  }
+
+  ///Returns everything written so far and empties the buffer.
+  pub fn take(&self) -> String {
+    std::mem::take(&mut *self.buf.lock().unwrap())
+  }
+}
+
+impl Output for BufferOutput {
+  fn write_char(&mut self, c:char) {
+    self.buf.lock().unwrap().push(c);
+  }
+
+  fn write_str(&mut self, s:&str) {
+    self.buf.lock().unwrap().push_str(s);
+  }
+}
+
+///Reads lines from a file.
+#[derive(Debug)]
+pub struct FileInput {
+  reader:BufReader<File>
+}
+
+impl FileInput {
+  pub fn open<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(FileInput { reader })
+  }
+}
+
+impl Input for FileInput {
+  fn read_line(&mut self) -> Option<String> {
+    let mut s = String::new();
+    match self.reader.read_line(&mut s) {
+      Ok(0) | Err(_) => None,
+      Ok(_) => Some(s)
+    }
+  }
+}
+
+///Writes output to a file. The file is flushed at the end of every line.
+/// Writing stops at the first error, which is kept and returned by
+/// [`Output::error`].
+#[derive(Debug)]
+pub struct FileOutput {
+  writer:LineWriter<File>,
+  error:Option<io::Error>
+}
+
+impl FileOutput {
+  pub fn create<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+    let writer = LineWriter::new(File::create(path)?);
+    Ok(FileOutput { writer, error:None })
+  }
+}
+
+impl Output for FileOutput {
+  fn write_char(&mut self, c:char) {
+    let mut buf = [0; 4];
+    self.write_str(c.encode_utf8(&mut buf));
+  }
+
+  fn write_str(&mut self, s:&str) {
+    if self.error.is_some() {
+      return;
+    }
+    if let Err(err) = self.writer.write_all(s.as_bytes()) {
+      self.error = Some(err);
+    }
+  }
+
+  fn error(&self) -> Option<&io::Error> {
+    self.error.as_ref()
+  }
+}
+
+///Receives lines from another thread. The source is exhausted once every
+/// [`Sender`] has been dropped.
+#[derive(Debug)]
+pub struct ChannelInput {
+  rx:Receiver<String>
+}
+
+impl ChannelInput {
+  pub fn new(rx:Receiver<String>) -> Self {
+    ChannelInput { rx }
+  }
+}
+
+impl Input for ChannelInput {
+  fn read_line(&mut self) -> Option<String> {
+    let mut line = self.rx.recv().ok()?;
+    if !line.ends_with('\n') {
+      line.push('\n');
+    }
+    Some(line)
+  }
+}
+
+///Sends output to another thread one line at a time.
+#[derive(Debug)]
+pub struct ChannelOutput {
+  tx:Sender<String>,
+  line:String
+}
+
+impl ChannelOutput {
+  pub fn new(tx:Sender<String>) -> Self {
+    ChannelOutput { tx, line:String::new() }
+  }
+}
+
+impl Output for ChannelOutput {
+  fn write_char(&mut self, c:char) {
+    self.line.push(c);
+    if c == '\n' {
+      //The receiver hanging up is not the VM's problem so the error is ignored
+      let _ = self.tx.send(std::mem::take(&mut self.line));
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{BufferInput, BufferOutput, ChannelInput, ChannelOutput, FileOutput, Output};
+  use crate::vm::VM;
+  use std::sync::mpsc::channel;
+
+  #[test]
+  fn buffer_output() {
+    //Out 'h', Out 'i', Halt
+    let mut vm = VM::new();
+    vm.mem = vec![19, 104, 19, 105, 0];
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+
+    vm.run().unwrap();
+    assert_eq!(out.contents(), "hi");
+  }
+
+  #[test]
+  fn file_output_error() {
+    //Every write to /dev/full fails once the line is flushed
+    let mut out = FileOutput::create("/dev/full").unwrap();
+    out.write_str("hi\n");
+    out.write_char('x');
+    assert!(out.error().is_some());
+  }
+
+  #[test]
+  fn buffer_input() {
+    //In R0, Out R0, In R0, Out R0, Halt
+    let mut vm = VM::new();
+    vm.mem = vec![20, 32768, 19, 32768, 20, 32768, 19, 32768, 0];
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["x"]));
+    vm.set_output(out.clone());
+
+    vm.run().unwrap();
+    assert_eq!(out.contents(), "x\n");
+  }
+
+  #[test]
+  fn channels() {
+    //In R0, Out R0, In R0, Out R0, Halt
+    let mut vm = VM::new();
+    vm.mem = vec![20, 32768, 19, 32768, 20, 32768, 19, 32768, 0];
+    let (in_tx, in_rx) = channel();
+    let (out_tx, out_rx) = channel();
+    vm.set_input(ChannelInput::new(in_rx));
+    vm.set_output(ChannelOutput::new(out_tx));
+
+    in_tx.send(String::from("y")).unwrap();
+    drop(in_tx);
+    vm.run().unwrap();
+    assert_eq!(out_rx.recv().unwrap(), "y\n");
+  }
+}
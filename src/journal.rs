@@ -0,0 +1,228 @@
+use crate::{disassembler::disassemble, vm::VM};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+///Number of executed instructions which can be stepped back over.
+pub const JOURNAL_LEN:usize = 100_000;
+
+///A change an instruction made, holding what is needed to reverse it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+  ///A register was written. Holds its old and new values.
+  Register(u8, u16, u16),
+  ///A memory address was written. Holds its old and new values.
+  Memory(u16, u16, u16),
+  ///A value was pushed onto the stack.
+  Push(u16),
+  ///A value was popped off the stack.
+  Pop(u16),
+  ///A character was taken from the pending input. Holds whether the `In`
+  /// opcode was partway through a line before.
+  Input(u8, bool),
+  ///A function was called.
+  Enter,
+  ///A function returned. Holds the entry address of the function, if it was
+  /// called after the [`VM`] started.
+  Leave(Option<u16>),
+  ///The [`VM`] stopped running.
+  Halt
+}
+
+///The changes made by one executed instruction.
+#[derive(Debug)]
+struct Entry {
+  pc:usize,
+  effects:Vec<Effect>
+}
+
+///Records the inverse of every instruction [`VM::dbg_run`] executes so the
+/// debugger can step backwards.
+#[derive(Debug, Default)]
+pub struct Journal {
+  entries:VecDeque<Entry>
+}
+
+impl Journal {
+  ///Returns the number of instructions which can be stepped back over.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  ///Start recording the instruction at `pc`.
+  pub(crate) fn begin(&mut self, pc:usize) {
+    if self.entries.len() == JOURNAL_LEN {
+      self.entries.pop_front();
+    }
+    self.entries.push_back(Entry { pc, effects:Vec::new() });
+  }
+}
+
+impl VM {
+  ///Record a change made by the instruction being executed in the history
+  /// and the journal. The trace reads it back from the history.
+  pub(crate) fn journal(&mut self, effect:Effect) {
+    self.history.push(effect);
+    self.journal_command(effect);
+  }
+
+  ///Record a change made by a system command in the journal only, so
+  /// stepping back over the last instruction undoes it but the history and
+  /// trace do not show it as that instruction's.
+  pub(crate) fn journal_command(&mut self, effect:Effect) {
+    if let Some(entry) = self.journal.as_mut().and_then(|journal| journal.entries.back_mut()) {
+      entry.effects.push(effect);
+    }
+  }
+
+  ///Reverse the last executed instruction. Returns false if there is nothing
+  /// left to reverse.
+  pub fn step_back(&mut self) -> bool {
+    let Some(entry) = self.journal.as_mut().and_then(|journal| journal.entries.pop_back())
+    else {
+      return false;
+    };
+
+    for effect in entry.effects.into_iter().rev() {
+      match effect {
+        Effect::Register(reg, val, _) => self.reg[reg as usize] = val,
+        Effect::Memory(addr, val, _) => self.mem[addr as usize] = val,
+        Effect::Push(_) => {
+          self.stack.pop();
+        }
+        Effect::Pop(val) => self.stack.push(val),
+        Effect::Input(c, mid_line) => {
+          self.inputs.push_front(c);
+          self.mid_line = mid_line;
+        }
+        Effect::Enter => {
+          self.frames.pop();
+        }
+        Effect::Leave(frame) => self.frames.extend(frame.map(usize::from)),
+        Effect::Halt => self.running = true
+      }
+    }
+    self.pc = entry.pc;
+    self.cycles -= 1;
+    true
+  }
+
+  ///Step back until the program counter reaches a breakpoint or the journal
+  /// runs out. Returns the number of instructions stepped back over.
+  pub fn reverse_continue(&mut self) -> usize {
+    let mut steps = 0;
+    while self.step_back() {
+      steps += 1;
+      if self.debugger.breakpoints.contains(&self.pc) {
+        break;
+      }
+    }
+    steps
+  }
+
+  ///Step back `n` instructions. Returns the number of instructions stepped
+  /// back over.
+  pub fn step_back_n(&mut self, n:usize) -> usize {
+    (0..n).take_while(|_| self.step_back()).count()
+  }
+
+  ///Run a backwards step for a system command and report where it stopped.
+  pub(crate) fn travel(&mut self, f:impl FnOnce(&mut VM) -> usize) {
+    if self.journal.is_none() {
+      self.output.write_str("Stepping back is only available while debugging\n");
+      return;
+    }
+
+    //Entered at the game's prompt so the In reading it has not finished.
+    //Reverse it and stop it from taking a character.
+    let reading = std::mem::take(&mut self.reading);
+    if reading {
+      self.step_back();
+    }
+
+    let steps = f(self);
+    let mut msg = match steps {
+      1 => String::from("Stepped back 1 instruction\n"),
+      steps => format!("Stepped back {steps} instructions\n")
+    };
+    match reading {
+      true => self.debugger.pause(),
+      false => msg += &format!("Stopped at {}", disassemble(&self.mem, self.pc, 1).trim_start())
+    }
+    self.output.write_str(&msg);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    assembler::assemble,
+    io::{BufferInput, BufferOutput},
+    test_programs::counter,
+    vm::VM
+  };
+
+  #[test]
+  fn step_back() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nadd r0 r0 1\npush r0\nwmem 100 r0\npop r1\nhalt").unwrap();
+    vm.mem.resize(101, 0);
+    vm.debugger.breakpoints.extend([3, 14]);
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new([
+      "continue",
+      "*stepback",
+      "reg 1",
+      "stack",
+      "*reverse-continue",
+      "reg 0",
+      "mem 100",
+      "continue",
+      "continue"
+    ]));
+    vm.set_output(out.clone());
+    vm.dbg_run().unwrap();
+
+    let out = out.contents();
+    assert!(out.contains("Stopped at 14: Halt\n(dbg) Stepped back 1 instruction\nStopped at 12: Pop r1\n(dbg) r1: 0\n(dbg) [2]\n"));
+    assert!(out.contains("(dbg) Stepped back 3 instructions\nStopped at 3: Add r0 r0 1\n(dbg) r0: 1\n(dbg) 100: [0]\n"));
+    assert_eq!((vm.reg[1], vm.mem[100]), (2, 2));
+    assert_eq!(vm.cycles, 6);
+  }
+
+  #[test]
+  fn step_back_over_input() {
+    let mut vm = VM::new();
+    vm.mem = assemble("in r0\nin r0\nhalt").unwrap();
+    vm.push_input(String::from("ab"));
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["step", "step", "reg 0", "*stepback 2", "reg 0", "continue"]));
+    vm.set_output(out.clone());
+    vm.debugger.pause();
+    vm.dbg_run().unwrap();
+
+    //The characters are read again after stepping back
+    let out = out.contents();
+    assert!(out.contains("(dbg) r0: 98\n(dbg) Stepped back 2 instructions\nStopped at 0: In r0\n(dbg) r0: 0\n"));
+    assert_eq!(vm.reg[0], 98);
+  }
+
+  #[test]
+  fn step_back_from_prompt() {
+    let mut vm = counter();
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["ab", "*stepback 4", "reg 0", "reg 2", "continue", "c"]));
+    vm.set_output(out.clone());
+    vm.dbg_run().unwrap_err();
+
+    //The In waiting for a command is abandoned and the debugger stops before
+    //the linebreak of the last command is read
+    assert_eq!(out.contents(), ">>Stepped back 4 instructions\nStopped at 2: In r0\n(dbg) r0: 98\n(dbg) r2: 2\n(dbg) >>");
+    assert_eq!(vm.reg[2], 3);
+  }
+}
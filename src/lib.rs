@@ -0,0 +1,24 @@
+pub mod assembler;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod coredump;
+pub mod debugger;
+pub mod disassembler;
+pub mod errors;
+pub mod helpers;
+pub mod history;
+pub mod instruction;
+pub mod io;
+pub mod journal;
+pub mod patch;
+pub mod profile;
+pub mod rewind;
+pub mod save;
+pub mod script;
+pub mod snapshot;
+#[cfg(test)]
+mod test_programs;
+pub mod trace;
+pub mod transcript;
+pub mod vm;
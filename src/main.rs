@@ -1,18 +1,9 @@
-use helpers::teleport_check;
-use vm::{VM, WORDSIZE};
-mod errors;
-mod helpers;
-mod vm;
+use clap::Parser;
+use vm::cli::Cli;
 
 fn main() {
-  let mut vm = VM::new();
-  vm.load().unwrap();
-
-  let mut cache = [[None; WORDSIZE as usize]; 5];
-  let check = teleport_check(4, 1, 25734, &mut cache);
-
-  if check == 6 {
-    vm.reg[7] = 25734;
-    vm.dbg_run().unwrap();
+  if let Err(err) = Cli::parse().run() {
+    eprintln!("{err:#}");
+    std::process::exit(1);
   }
 }
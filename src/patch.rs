@@ -0,0 +1,133 @@
+use crate::{errors::VMErrors, journal::Effect, vm::VM};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+///A change to a register or memory applied by a system command. Applied
+/// patches are kept with the [`VM`] so they are saved with the game and
+/// re-applied when it is loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Patch {
+  ///Set a register to a value.
+  Register { reg:u8, val:u16 },
+  ///Write a run of words into memory starting at an address.
+  Memory { addr:u16, vals:Vec<u16> }
+}
+
+impl Patch {
+  ///Apply the [`Patch`] to a [`VM`]. Errors without changing anything if a
+  /// memory patch runs past the end of memory.
+  pub fn apply(&self, vm:&mut VM) -> Result<(), VMErrors> {
+    match self {
+      Patch::Register { reg, val } => vm.reg[*reg as usize] = *val,
+      Patch::Memory { addr, vals } => {
+        let start = *addr as usize;
+        let end = start + vals.len();
+        if end > vm.mem.len() {
+          return Err(VMErrors::OutOfBounds(end - 1));
+        }
+        vm.mem[start..end].copy_from_slice(vals);
+      }
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Patch {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Patch::Register { reg, val } => write!(f, "r{reg} = {val}"),
+      Patch::Memory { addr, vals } => write!(f, "mem[{addr}] = {vals:?}")
+    }
+  }
+}
+
+impl VM {
+  ///Apply a [`Patch`] and add it to the log of applied patches. The changes
+  /// are journaled so stepping back undoes them, but kept out of the history.
+  pub fn poke(&mut self, patch:Patch) -> Result<(), VMErrors> {
+    let effects = match &patch {
+      Patch::Register { reg, val } => vec![Effect::Register(*reg, self.reg[*reg as usize], *val)],
+      Patch::Memory { addr, vals } => (*addr as usize..)
+        .zip(vals)
+        .filter_map(|(addr, &val)| self.mem.get(addr).map(|&old| Effect::Memory(addr as u16, old, val)))
+        .collect()
+    };
+    patch.apply(self)?;
+    effects.into_iter().for_each(|effect| self.journal_command(effect));
+    self.patches.push(patch);
+    Ok(())
+  }
+
+  ///Re-apply every logged [`Patch`] in the order they were first applied.
+  pub fn reapply_patches(&mut self) -> Result<(), VMErrors> {
+    for patch in self.patches.clone() {
+      patch.apply(self)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Patch;
+  use crate::{
+    assembler::assemble,
+    io::{BufferInput, BufferOutput},
+    vm::VM
+  };
+
+  #[test]
+  fn poke() {
+    let mut vm = VM::new();
+    vm.mem = vec![0; 8];
+    vm.poke(Patch::Register { reg:7, val:25734 }).unwrap();
+    vm.poke(Patch::Memory { addr:2, vals:vec![21, 21, 21] }).unwrap();
+    assert!(vm.poke(Patch::Memory { addr:6, vals:vec![1, 2, 3] }).is_err());
+
+    assert_eq!(vm.reg[7], 25734);
+    assert_eq!(vm.mem, [0, 0, 21, 21, 21, 0, 0, 0]);
+    assert_eq!(vm.patches.len(), 2);
+
+    //Re-applying restores the patched values
+    vm.reg[7] = 0;
+    vm.mem[3] = 0;
+    vm.reapply_patches().unwrap();
+    assert_eq!(vm.reg[7], 25734);
+    assert_eq!(vm.mem[3], 21);
+  }
+
+  #[test]
+  fn reapplied_on_load() {
+    let dir = std::env::temp_dir().join("vm_patch_load_test");
+    let mut vm = VM::new();
+    vm.mem = vec![0; 8];
+    vm.config.save_dir = dir.clone();
+    vm.set_output(BufferOutput::new());
+    vm.exe_system_commands(String::from("*reg 7 25734"));
+    vm.exe_system_commands(String::from("*poke 2 21 21"));
+
+    //The game overwrites the patched values before saving
+    vm.reg[7] = 1;
+    vm.mem[3] = 0;
+    vm.exe_system_commands(String::from("*save patched"));
+    vm.exe_system_commands(String::from("*load patched"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(vm.reg[7], 25734);
+    assert_eq!(vm.mem[2..4], [21, 21]);
+    assert_eq!(vm.patches.len(), 2);
+  }
+
+  #[test]
+  fn step_back() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nset r1 2\nhalt").unwrap();
+    vm.mem.resize(9, 0);
+    vm.debugger.breakpoints.insert(3);
+    vm.set_input(BufferInput::new(["*reg 7 9", "*poke 8 5", "*stepback", "quit"]));
+    vm.set_output(BufferOutput::new());
+    vm.dbg_run().unwrap();
+
+    //Stepping back over the last instruction also undoes the pokes made after it
+    assert_eq!((vm.pc, vm.reg[0], vm.reg[7], vm.mem[8]), (0, 0, 0, 0));
+  }
+}
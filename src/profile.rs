@@ -0,0 +1,244 @@
+use crate::{
+  disassembler::format_instruction,
+  instruction::decode,
+  vm::{OpCode, VM}
+};
+use std::{collections::HashMap, fmt::Write};
+
+///Number of rows in each table of the profile report.
+pub const REPORT_ROWS:usize = 15;
+
+///Number of [`OpCode`]s.
+const OPCODES:usize = OpCode::Noop as usize + 1;
+
+///Instructions executed inside a function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+  ///Number of times the function was called.
+  pub calls:u64,
+  ///Instructions executed by the function itself.
+  pub own:u64,
+  ///Instructions executed by the function and the functions it calls while
+  /// every call to it had returned. Recursive calls are only counted once.
+  returned:u64,
+  ///Number of calls to the function which have not returned.
+  active:usize,
+  ///Instruction count when the outermost active call was made.
+  entered:u64
+}
+
+///Counts the instructions the [`VM`] executes by address, [`OpCode`] and the
+/// function they run in. Functions are tracked by their `Call` and `Ret`
+/// instructions.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+  ///Instructions executed at each address.
+  pub by_pc:Vec<u64>,
+  ///Instructions executed for each [`OpCode`].
+  pub by_op:[u64; OPCODES],
+  ///Stats of every function called, keyed by its entry address.
+  pub functions:HashMap<usize, FunctionStats>,
+  ///Instructions executed outside of any call made while profiling.
+  pub top_level:u64,
+  ///Instructions executed while profiling.
+  pub total:u64
+}
+
+///A change to profiling made with `*profile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSetting {
+  Report,
+  Start,
+  Stop,
+  Reset
+}
+
+impl Default for Profiler {
+  fn default() -> Self {
+    Profiler {
+      by_pc:Vec::new(),
+      by_op:[0; OPCODES],
+      functions:HashMap::new(),
+      top_level:0,
+      total:0
+    }
+  }
+}
+
+impl Profiler {
+  ///Count an instruction about to execute inside the function entered at
+  /// `frame`.
+  pub(crate) fn count(&mut self, pc:usize, op:OpCode, frame:Option<usize>) {
+    self.total += 1;
+    if pc >= self.by_pc.len() {
+      self.by_pc.resize(pc + 1, 0);
+    }
+    self.by_pc[pc] += 1;
+    self.by_op[op as usize] += 1;
+    match frame {
+      Some(frame) => self.functions.entry(frame).or_default().own += 1,
+      None => self.top_level += 1
+    }
+  }
+
+  ///Start a call to the function at `addr`.
+  pub(crate) fn enter(&mut self, addr:usize) {
+    let stats = self.functions.entry(addr).or_default();
+    stats.calls += 1;
+    if stats.active == 0 {
+      stats.entered = self.total;
+    }
+    stats.active += 1;
+  }
+
+  ///Return from the function at `addr`.
+  pub(crate) fn leave(&mut self, addr:usize) {
+    if let Some(stats) = self.functions.get_mut(&addr).filter(|stats| stats.active > 0) {
+      stats.active -= 1;
+      if stats.active == 0 {
+        stats.returned += self.total - stats.entered;
+      }
+    }
+  }
+
+  ///Returns the instructions executed by a function and the functions it
+  /// calls, including calls which have not returned yet.
+  pub fn inclusive(&self, stats:&FunctionStats) -> u64 {
+    match stats.active {
+      0 => stats.returned,
+      _ => stats.returned + self.total - stats.entered
+    }
+  }
+
+  ///Returns `count` as a percentage of every instruction profiled.
+  fn percent(&self, count:u64) -> f64 {
+    100.0 * count as f64 / self.total.max(1) as f64
+  }
+}
+
+impl VM {
+  ///Returns a report of the [`Profiler`]'s hottest addresses, [`OpCode`]s and
+  /// functions, hottest first.
+  pub fn profile_report(&self) -> String {
+    let Some(profiler) = &self.profiler
+    else {
+      return String::from("Profiling is off. Use `*profile start` to turn it on\n");
+    };
+
+    let mut s = format!("Profiled {} instructions\n", profiler.total);
+
+    let mut pcs = profiler.by_pc.iter().enumerate().filter(|(_, count)| **count > 0).collect::<Vec<(usize, &u64)>>();
+    pcs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(&b.0)));
+    writeln!(s, "Hottest addresses:").unwrap();
+    for (pc, &count) in pcs.into_iter().take(REPORT_ROWS) {
+      let inst = decode(&self.mem, pc).map_or_else(|_| String::from("?"), |inst| format_instruction(&inst));
+      writeln!(s, "{pc:>7} {count:>12} {:>6.2}%  {inst}", profiler.percent(count)).unwrap();
+    }
+
+    let mut ops = (0..OPCODES as u16)
+      .filter_map(|code| OpCode::new(code).ok())
+      .filter(|op| profiler.by_op[*op as usize] > 0)
+      .collect::<Vec<OpCode>>();
+    ops.sort_by_key(|op| std::cmp::Reverse(profiler.by_op[*op as usize]));
+    writeln!(s, "Opcodes:").unwrap();
+    for op in ops {
+      let count = profiler.by_op[op as usize];
+      writeln!(s, "{:>7} {count:>12} {:>6.2}%", format!("{op:?}"), profiler.percent(count)).unwrap();
+    }
+
+    let mut functions = profiler
+      .functions
+      .iter()
+      .map(|(addr, stats)| (*addr, *stats, profiler.inclusive(stats)))
+      .collect::<Vec<(usize, FunctionStats, u64)>>();
+    functions.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.own.cmp(&a.1.own)).then(a.0.cmp(&b.0)));
+    writeln!(s, "Functions by instructions executed inside them:").unwrap();
+    writeln!(s, "{:>7} {:>12} {:>7}  {:>12} {:>7}  {:>10}", "addr", "total", "", "own", "", "calls").unwrap();
+    for (addr, stats, inclusive) in functions.into_iter().take(REPORT_ROWS) {
+      writeln!(
+        s,
+        "{addr:>7} {inclusive:>12} {:>6.2}%  {:>12} {:>6.2}%  {:>10}",
+        profiler.percent(inclusive),
+        stats.own,
+        profiler.percent(stats.own),
+        stats.calls
+      )
+      .unwrap();
+    }
+    writeln!(s, "{:>7} {:>12} {:>6.2}%", "top", profiler.top_level, profiler.percent(profiler.top_level)).unwrap();
+    s
+  }
+
+  ///Print the profile report.
+  pub fn print_profile(&mut self) {
+    let report = self.profile_report();
+    self.output.write_str(&report);
+  }
+
+  ///Turn profiling on or off, or print the report.
+  pub(crate) fn set_profiling(&mut self, setting:ProfileSetting) {
+    match setting {
+      ProfileSetting::Report => self.print_profile(),
+      ProfileSetting::Start => {
+        self.profiler.get_or_insert_with(Profiler::default);
+        self.output.write_str("Profiling\n");
+      }
+      ProfileSetting::Stop => {
+        self.print_profile();
+        self.profiler = None;
+      }
+      ProfileSetting::Reset => {
+        self.profiler = Some(Profiler::default());
+        self.output.write_str("Profiling from now\n");
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    assembler::assemble,
+    io::BufferOutput,
+    vm::{OpCode, VM}
+  };
+
+  #[test]
+  fn profile() {
+    let mut vm = VM::new();
+    vm.mem = assemble(
+      "
+            call f
+            halt
+      f:    set r0 3
+      loop: call g
+            jt r0 loop
+            ret
+      g:    add r0 r0 32767
+            ret
+      "
+    )
+    .unwrap();
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    vm.exe_system_commands(String::from("*profile start"));
+    vm.run().unwrap();
+
+    let profiler = vm.profiler.as_ref().unwrap();
+    assert_eq!(profiler.total, 16);
+    assert_eq!((profiler.by_pc[12], profiler.by_pc[6]), (3, 3));
+    assert_eq!(profiler.by_op[OpCode::Ret as usize], 4);
+    let f = profiler.functions[&3];
+    assert_eq!((f.calls, f.own, profiler.inclusive(&f)), (1, 8, 14));
+    let g = profiler.functions[&12];
+    assert_eq!((g.calls, g.own, profiler.inclusive(&g)), (3, 6, 6));
+    assert_eq!(profiler.top_level, 2);
+
+    vm.exe_system_commands(String::from("*profile stop"));
+    let report = out.contents();
+    assert!(report.starts_with("Profiling\nProfiled 16 instructions\nHottest addresses:\n"));
+    assert!(report.contains("     12            3  18.75%  Add r0 r0 32767\n"));
+    assert!(report.contains("      3           14  87.50%             8  50.00%           1\n"));
+    assert!(vm.profiler.is_none());
+  }
+}
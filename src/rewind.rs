@@ -0,0 +1,87 @@
+use crate::{
+  errors::VMErrors,
+  vm::{OpCode, VM}
+};
+
+///Number of commands which can be undone.
+pub const CHECKPOINTS:usize = 256;
+
+impl VM {
+  ///Take a checkpoint to return to with `*undo`. Called by the `In` opcode
+  /// before it reads the first character of a line. The checkpoint points at
+  /// the `In` and drops the pending input so restoring it waits for a new
+  /// command.
+  pub(crate) fn checkpoint(&mut self) {
+    let inputs = std::mem::take(&mut self.inputs);
+    let pc = self.pc;
+    self.pc -= OpCode::In.arity() + 1;
+    let snapshot = self.snapshot();
+    self.pc = pc;
+    self.inputs = inputs;
+
+    if self.checkpoints.len() == CHECKPOINTS {
+      self.checkpoints.pop_front();
+    }
+    self.checkpoints.push_back(snapshot);
+  }
+
+  ///Return to the state before the last `n` commands were entered.
+  /// Rewinding 0 commands does nothing.
+  pub fn rewind(&mut self, n:usize) -> Result<(), VMErrors> {
+    if n == 0 {
+      return Ok(());
+    }
+    //While the `In` opcode is reading the newest checkpoint is the prompt
+    //this command was entered at
+    let available = self.checkpoints.len().saturating_sub(self.reading as usize);
+    if n > available {
+      return Err(VMErrors::RewindTooFar { requested:n, available });
+    }
+
+    //Restoring re-runs the `In` which takes the checkpoint again
+    let target = available - n;
+    let snapshot = self.checkpoints[target].clone();
+    self.checkpoints.truncate(target);
+    self.restore(&snapshot)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    errors::VMErrors,
+    io::{BufferInput, BufferOutput},
+    test_programs::counter
+  };
+
+  #[test]
+  fn undo_and_rewind() {
+    let mut vm = counter();
+
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["ab", "c", "*undo", "de", "*rewind 2", "f", "*rewind 5"]));
+    vm.set_output(out.clone());
+
+    assert_eq!(vm.run().unwrap_err().kind, VMErrors::InputEof);
+    assert_eq!(vm.reg[2], 1);
+    assert_eq!(
+      out.contents(),
+      ">>>Undid the last command\n>Rewound 2 commands\n>Cannot rewind 5 commands. Only 1 commands can be undone.\n"
+    );
+  }
+
+  #[test]
+  fn undo_between_commands() {
+    let mut vm = counter();
+    vm.run_until_input(100);
+    vm.push_input(String::from("ab\n"));
+    vm.run_until_input(100);
+    assert_eq!(vm.reg[2], 2);
+
+    //The VM stopped before the next prompt's In so it has no checkpoint yet
+    vm.rewind(1).unwrap();
+    assert_eq!(vm.reg[2], 0);
+    assert!(vm.rewind(1).is_err());
+    vm.rewind(0).unwrap();
+  }
+}
@@ -0,0 +1,366 @@
+use crate::{
+  errors::VMErrors,
+  snapshot::{find_section, is_snapshot, push_section},
+  vm::VM
+};
+use clap::ValueEnum;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs::{self, File},
+  io::{BufWriter, ErrorKind},
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH}
+};
+
+///Version of the save format written by [`write_save`]. Bump it whenever
+/// the saved [`VM`] state changes in a way older saves cannot be read.
+pub const SAVE_VERSION:u32 = 1;
+
+///Directory save slots are kept in unless the [`VM`] is given another.
+pub const SAVE_DIR:&str = "saves";
+
+///Tag of the snapshot section holding a binary save's [`SaveHeader`].
+const HEADER:[u8; 4] = *b"SAVE";
+
+///How save slots are written. Slots of either format can be loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveFormat {
+  ///Readable JSON, useful for debugging.
+  #[default]
+  Json,
+  ///A compact binary snapshot which is smaller and faster to load.
+  Binary
+}
+
+impl SaveFormat {
+  const ALL:[SaveFormat; 2] = [SaveFormat::Json, SaveFormat::Binary];
+
+  ///Returns the file extension of save slots in this format.
+  pub fn extension(self) -> &'static str {
+    match self {
+      SaveFormat::Json => "json",
+      SaveFormat::Binary => "bin"
+    }
+  }
+
+  ///Returns the format of a save file from its extension.
+  fn of(path:&Path) -> Self {
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("bin") => SaveFormat::Binary,
+      _ => SaveFormat::Json
+    }
+  }
+}
+
+///Describes a save file. Read before the rest of the file so unsupported
+/// saves are rejected without trying to parse the [`VM`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveHeader {
+  pub version:u32,
+  ///Version of the crate which wrote the save.
+  pub vm_version:String,
+  ///Seconds since the Unix epoch when the save was written.
+  pub saved_at:u64,
+  ///Number of instructions the [`VM`] had executed.
+  #[serde(default)]
+  pub cycles:u64,
+  ///Title of the last room the game printed.
+  #[serde(default)]
+  pub room:Option<String>
+}
+
+impl SaveHeader {
+  fn new(vm:&VM) -> Self {
+    SaveHeader {
+      version:SAVE_VERSION,
+      vm_version:env!("CARGO_PKG_VERSION").to_string(),
+      saved_at:SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default(),
+      cycles:vm.cycles,
+      room:vm.room.clone()
+    }
+  }
+}
+
+///A save file. The [`VM`] is borrowed when writing and owned when reading.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Save<V> {
+  pub header:SaveHeader,
+  pub vm:V
+}
+
+///The part of a save file needed to check its version.
+#[derive(Deserialize)]
+struct Versioned {
+  header:Option<Version>
+}
+
+#[derive(Deserialize)]
+struct Version {
+  version:u32
+}
+
+///The header of a save file without the [`VM`].
+#[derive(Deserialize)]
+struct HeaderOnly {
+  header:SaveHeader
+}
+
+///A save slot found by [`list_saves`].
+#[derive(Debug)]
+pub struct SaveSlot {
+  pub name:String,
+  ///The slot's header or why it could not be read.
+  pub header:Result<SaveHeader, String>
+}
+
+///Returns true if `name` can name a save slot. Slot names are made of ASCII
+/// letters, digits, `-` and `_` so they cannot point outside the save
+/// directory.
+pub fn is_slot_name(name:&str) -> bool {
+  !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+///Returns the path of the save slot `name` in `dir` when written in
+/// `format`.
+pub fn slot_path(dir:&Path, name:&str, format:SaveFormat) -> PathBuf {
+  dir.join(format!("{name}.{}", format.extension()))
+}
+
+///Returns the path of the existing save slot `name` in `dir`, in either
+/// format.
+pub fn find_slot(dir:&Path, name:&str) -> Option<PathBuf> {
+  SaveFormat::ALL.into_iter().map(|format| slot_path(dir, name, format)).find(|path| path.is_file())
+}
+
+///Write the save slot `name` in `dir`, replacing the slot if it was saved in
+/// the other format.
+pub fn write_slot(vm:&VM, dir:&Path, name:&str, format:SaveFormat) -> Result<()> {
+  write_save(vm, slot_path(dir, name, format))?;
+  for other in SaveFormat::ALL.into_iter().filter(|other| *other != format) {
+    match fs::remove_file(slot_path(dir, name, other)) {
+      Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+      _ => {}
+    }
+  }
+  Ok(())
+}
+
+///Write the complete state of a [`VM`] to `path`. Paths ending in `.bin` are
+/// written as a binary snapshot, others as JSON.
+pub fn write_save<P:AsRef<Path>>(vm:&VM, path:P) -> Result<()> {
+  let path = path.as_ref();
+  let save = Save { header:SaveHeader::new(vm), vm };
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  match SaveFormat::of(path) {
+    SaveFormat::Json => serde_json::to_writer(BufWriter::new(File::create(path)?), &save)?,
+    SaveFormat::Binary => {
+      //Memory is stored whole so the save does not depend on the program binary
+      let mut snapshot = vm.to_snapshot(None);
+      push_section(&mut snapshot, HEADER, &serde_json::to_vec(&save.header)?);
+      fs::write(path, snapshot)?;
+    }
+  }
+  Ok(())
+}
+
+///Read a save written by [`write_save`] in either format. Errors if the save
+/// was written in a different version of the format.
+pub fn read_save<P:AsRef<Path>>(path:P) -> Result<Save<VM>> {
+  let bytes = fs::read(path)?;
+  if is_snapshot(&bytes) {
+    let header = binary_header(&bytes)?;
+    return Ok(Save {
+      header,
+      vm:VM::from_snapshot(&bytes, None)?
+    });
+  }
+  let s = String::from_utf8(bytes)?;
+  check_version(&s)?;
+  Ok(serde_json::from_str(&s)?)
+}
+
+///Read only the [`SaveHeader`] of a save.
+pub fn read_header<P:AsRef<Path>>(path:P) -> Result<SaveHeader> {
+  let bytes = fs::read(path)?;
+  if is_snapshot(&bytes) {
+    return binary_header(&bytes);
+  }
+  let s = String::from_utf8(bytes)?;
+  check_version(&s)?;
+  Ok(serde_json::from_str::<HeaderOnly>(&s)?.header)
+}
+
+///Read and check the [`SaveHeader`] of a binary save.
+fn binary_header(bytes:&[u8]) -> Result<SaveHeader> {
+  let Some(header) = find_section(bytes, HEADER)?
+  else {
+    return Err(VMErrors::UnversionedSave.into());
+  };
+  let header = serde_json::from_slice::<SaveHeader>(header)?;
+  match header.version {
+    SAVE_VERSION => Ok(header),
+    version => Err(VMErrors::UnsupportedSave { version, expected:SAVE_VERSION }.into())
+  }
+}
+
+fn check_version(s:&str) -> Result<()> {
+  match serde_json::from_str::<Versioned>(s)?.header {
+    Some(Version { version }) if version == SAVE_VERSION => Ok(()),
+    Some(Version { version }) => Err(VMErrors::UnsupportedSave { version, expected:SAVE_VERSION }.into()),
+    None => Err(VMErrors::UnversionedSave.into())
+  }
+}
+
+///List the save slots in `dir`, newest first. Slots which cannot be read are
+/// listed last. A missing directory has no slots.
+pub fn list_saves(dir:&Path) -> Result<Vec<SaveSlot>> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(err) => return Err(err.into())
+  };
+
+  let mut slots = Vec::new();
+  for entry in entries {
+    let path = entry?.path();
+    let Some(name) = path.file_stem().and_then(|name| name.to_str()).filter(|name| is_slot_name(name))
+    else {
+      continue;
+    };
+    if !SaveFormat::ALL.into_iter().any(|format| path.extension().and_then(|ext| ext.to_str()) == Some(format.extension())) {
+      continue;
+    }
+
+    let header = read_header(&path).map_err(|err| err.to_string());
+    slots.push(SaveSlot { name:name.to_string(), header });
+  }
+
+  slots.sort_by(|a, b| match (&a.header, &b.header) {
+    (Ok(a), Ok(b)) => b.saved_at.cmp(&a.saved_at),
+    (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+    (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+    (Err(_), Err(_)) => a.name.cmp(&b.name)
+  });
+  Ok(slots)
+}
+
+///Format seconds since the Unix epoch as a UTC date and time.
+pub fn fmt_timestamp(secs:u64) -> String {
+  let days = (secs / 86400) as i64;
+  let time = secs % 86400;
+
+  //Convert days since the epoch to a civil date
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + (month <= 2) as i64;
+
+  format!("{year:04}-{month:02}-{day:02} {:02}:{:02} UTC", time / 3600, time % 3600 / 60)
+}
+
+#[cfg(test)]
+mod test {
+  use super::{fmt_timestamp, list_saves, read_save, slot_path, write_save, write_slot, SaveFormat, SAVE_VERSION};
+  use crate::{errors::VMErrors, io::BufferInput, vm::VM};
+  use std::fs;
+
+  #[test]
+  fn round_trip() {
+    let path = std::env::temp_dir().join("vm_save_test.json");
+
+    //In R0, Out R0, Halt
+    let mut vm = VM::new();
+    vm.mem = vec![20, 32768, 19, 32768, 0];
+    vm.reg[7] = 25734;
+    vm.push_input(String::from("ab"));
+    vm.debugger.breakpoints.insert(2);
+    write_save(&vm, &path).unwrap();
+
+    let save = read_save(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(save.header.version, SAVE_VERSION);
+    assert_eq!(save.header.cycles, 0);
+    assert_eq!(save.vm.reg[7], 25734);
+    assert_eq!(save.vm.mem, vm.mem);
+    assert!(save.vm.debugger.breakpoints.contains(&2));
+
+    //The pending input survives the save
+    let mut vm = save.vm;
+    vm.set_input(BufferInput::new(Vec::<String>::new()));
+    let outcome = vm.run_until_input(10);
+    assert_eq!(outcome.output, "a");
+  }
+
+  #[test]
+  fn binary() {
+    let path = std::env::temp_dir().join("vm_save_test.bin");
+
+    let mut vm = VM::new();
+    vm.mem = vec![20, 32768, 19, 32768, 0];
+    vm.cycles = 99;
+    vm.push_input(String::from("ab"));
+    write_save(&vm, &path).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    let save = read_save(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(bytes.starts_with(b"SVMS"));
+    assert_eq!((save.header.version, save.header.cycles), (SAVE_VERSION, 99));
+    assert_eq!((save.vm.mem, save.vm.inputs), (vm.mem, vm.inputs));
+  }
+
+  #[test]
+  fn unsupported_versions() {
+    let path = std::env::temp_dir().join("vm_save_version_test.json");
+
+    fs::write(&path, r#"{"reg":[0,0,0,0,0,0,0,0],"mem":[],"stack":[],"pc":0,"running":true,"inputs":[],"debug":0}"#).unwrap();
+    let err = read_save(&path).unwrap_err();
+    assert!(matches!(err.downcast_ref::<VMErrors>(), Some(VMErrors::UnversionedSave)));
+
+    fs::write(&path, r#"{"header":{"version":0,"vm_version":"0.1.0","saved_at":0},"vm":{}}"#).unwrap();
+    let err = read_save(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(err.downcast_ref::<VMErrors>(), Some(VMErrors::UnsupportedSave { version:0, .. })));
+  }
+
+  #[test]
+  fn slots() {
+    let dir = std::env::temp_dir().join("vm_save_slots_test");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(list_saves(&dir).unwrap().is_empty());
+
+    let mut vm = VM::new();
+    vm.cycles = 1234;
+    vm.room = Some(String::from("Foothills"));
+    write_slot(&vm, &dir, "start", SaveFormat::Json).unwrap();
+    //Saving in the other format replaces the slot
+    write_slot(&vm, &dir, "start", SaveFormat::Binary).unwrap();
+    assert!(!slot_path(&dir, "start", SaveFormat::Json).exists());
+    fs::write(slot_path(&dir, "old", SaveFormat::Json), "{}").unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+
+    let slots = list_saves(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(slots[0].name, "start");
+    let header = slots[0].header.as_ref().unwrap();
+    assert_eq!((header.cycles, header.room.as_deref()), (1234, Some("Foothills")));
+    assert_eq!(slots[1].name, "old");
+    assert!(slots[1].header.is_err());
+  }
+
+  #[test]
+  fn timestamps() {
+    assert_eq!(fmt_timestamp(0), "1970-01-01 00:00 UTC");
+    assert_eq!(fmt_timestamp(951_827_696), "2000-02-29 12:34 UTC");
+  }
+}
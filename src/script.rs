@@ -0,0 +1,187 @@
+use crate::{
+  commands::Command,
+  errors::VMErrors,
+  vm::{RunStatus, VM}
+};
+use eyre::Result;
+
+///Number of instructions one line of a script may run before it is treated
+/// as stuck.
+pub const SCRIPT_CYCLES:usize = 50_000_000;
+
+///A line of a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+  ///A line entered into the game.
+  Game(String),
+  ///A system command, written with a leading `*`.
+  System(Command),
+  ///`expect "text"`. Fails the script unless the game printed `text` in
+  /// response to the previous line.
+  Expect(String)
+}
+
+///A parsed script along with the line each [`Step`] came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+  pub steps:Vec<(usize, Step)>
+}
+
+fn script_err(line:usize, msg:impl Into<String>) -> VMErrors {
+  VMErrors::Script { line, msg:msg.into() }
+}
+
+///Parse the quoted text of an `expect` directive. Supports the escapes `\"`,
+/// `\\` and `\n`.
+fn parse_quoted(s:&str, line:usize) -> Result<String, VMErrors> {
+  let Some(s) = s.strip_prefix('"')
+  else {
+    return Err(script_err(line, "expected text in double quotes"));
+  };
+
+  let mut text = String::new();
+  let mut chars = s.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if chars.as_str().trim().is_empty() => return Ok(text),
+      '"' => return Err(script_err(line, "unexpected text after the closing quote")),
+      '\\' => match chars.next() {
+        Some('n') => text.push('\n'),
+        Some(c @ ('"' | '\\')) => text.push(c),
+        _ => return Err(script_err(line, "unknown escape sequence"))
+      },
+      c => text.push(c)
+    }
+  }
+  Err(script_err(line, "missing the closing quote"))
+}
+
+impl std::str::FromStr for Script {
+  type Err = VMErrors;
+
+  ///Parse a script. Each line is a game command, a system command starting
+  /// with `*` or an `expect "text"` directive. Blank lines and lines starting
+  /// with `#` are ignored.
+  fn from_str(s:&str) -> Result<Self, Self::Err> {
+    let mut steps = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+      let line_no = i + 1;
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let step = if line.starts_with('*') {
+        Step::System(line.parse().map_err(|err:VMErrors| script_err(line_no, err.to_string()))?)
+      }
+      else if let Some(text) = line.strip_prefix("expect ") {
+        Step::Expect(parse_quoted(text.trim_start(), line_no)?)
+      }
+      else {
+        Step::Game(line.to_string())
+      };
+      steps.push((line_no, step));
+    }
+    Ok(Script { steps })
+  }
+}
+
+impl VM {
+  ///Run a [`Script`] without reading from the [`VM`]'s
+  /// [`Input`](crate::io::Input). The game's text is printed to the [`VM`]'s
+  /// [`Output`](crate::io::Output) as it runs. Stops at the first `expect`
+  /// which does not match, or if the [`VM`] faults, halts or gets stuck.
+  pub fn run_script(&mut self, script:&Script) -> Result<()> {
+    //The text printed since the last game or system command
+    let mut printed = self.run_script_line(0)?;
+
+    for (line, step) in &script.steps {
+      match step {
+        Step::Game(s) => {
+          if !self.running {
+            return Err(script_err(*line, "the VM halted before the script finished").into());
+          }
+          self.push_input(format!("{s}\n"));
+          printed = self.run_script_line(*line)?;
+        }
+        Step::System(cmd) => {
+          self.exe_command(cmd.clone());
+          //Commands like `*solve` feed lines to the game
+          printed = self.run_script_line(*line)?;
+        }
+        Step::Expect(text) => {
+          if !printed.contains(text.as_str()) {
+            return Err(VMErrors::ExpectFailed { line:*line, expected:text.clone() }.into());
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  ///Run until the game needs another line and return what it printed.
+  fn run_script_line(&mut self, line:usize) -> Result<String> {
+    let outcome = self.run_until_input(SCRIPT_CYCLES);
+    self.output.write_str(&outcome.output);
+    match outcome.status {
+      RunStatus::WaitingForInput | RunStatus::Halted => Ok(outcome.output),
+      RunStatus::Faulted(fault) => Err(eyre::eyre!(self.fault_report(&fault))),
+      RunStatus::OutOfCycles => Err(script_err(line, format!("the game did not ask for input within {SCRIPT_CYCLES} instructions")).into())
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Script, Step};
+  use crate::{commands::Command, errors::VMErrors, io::BufferOutput, test_programs::echo};
+
+  #[test]
+  fn parse() {
+    let script:Script = "# Walkthrough\n\ntake tablet\n  expect \"say \\\"hi\\\"\\n\"\n*undo\n".parse().unwrap();
+    assert_eq!(
+      script.steps,
+      vec![
+        (3, Step::Game(String::from("take tablet"))),
+        (4, Step::Expect(String::from("say \"hi\"\n"))),
+        (5, Step::System(Command::Rewind(1)))
+      ]
+    );
+
+    for (script, line) in [("look\n*fly", 2), ("expect hi", 1), ("\n\nexpect \"hi", 3), ("expect \"a\" b", 1)] {
+      assert!(matches!(script.parse::<Script>(), Err(VMErrors::Script { line:l, .. }) if l == line));
+    }
+  }
+
+  #[test]
+  fn run() {
+    let mut vm = echo();
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    let script = "expect \">\"\nhello\nexpect \"hello\"\n*undo\nbye\nexpect \"bye\"\nq".parse().unwrap();
+    vm.run_script(&script).unwrap();
+    assert_eq!(out.contents(), ">hello\n>Undid the last command\nbye\n>");
+    assert!(!vm.running);
+  }
+
+  #[test]
+  fn failures() {
+    let script = "hello\nexpect \"goodbye\"".parse().unwrap();
+    let err = echo().run_script(&script).unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<VMErrors>(),
+      Some(&VMErrors::ExpectFailed {
+        line:2,
+        expected:String::from("goodbye")
+      })
+    );
+
+    //Output from before the previous line does not count
+    let script = "hello\nbye\nexpect \"hello\"".parse().unwrap();
+    assert!(echo().run_script(&script).is_err());
+
+    let script = "q\nhello".parse().unwrap();
+    let err = echo().run_script(&script).unwrap_err();
+    assert!(matches!(err.downcast_ref::<VMErrors>(), Some(VMErrors::Script { line:2, .. })));
+  }
+}
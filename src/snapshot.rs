@@ -0,0 +1,368 @@
+use crate::{
+  debugger::Debugger,
+  disassembler::words,
+  errors::VMErrors,
+  patch::Patch,
+  vm::{VM, WORDSIZE}
+};
+use std::{collections::VecDeque, ops::Range};
+
+///Marks the start of a snapshot.
+const MAGIC:&[u8; 4] = b"SVMS";
+
+///Version of the snapshot encoding.
+pub const SNAPSHOT_VERSION:u32 = 1;
+
+//Section tags
+const REGS:[u8; 4] = *b"REGS";
+const CPU:[u8; 4] = *b"CPU ";
+const STACK:[u8; 4] = *b"STAK";
+const MEM:[u8; 4] = *b"MEM ";
+const MEM_DELTA:[u8; 4] = *b"MDLT";
+const INPUT:[u8; 4] = *b"INPT";
+const ROOM:[u8; 4] = *b"ROOM";
+const PATCHES:[u8; 4] = *b"PTCH";
+const DEBUGGER:[u8; 4] = *b"DBUG";
+
+///Builds a snapshot out of sections. Each section is a 4 byte tag and a
+/// little-endian `u32` length followed by that many bytes.
+struct Writer {
+  buf:Vec<u8>
+}
+
+impl Writer {
+  fn new() -> Self {
+    let mut buf = Vec::from(*MAGIC);
+    buf.extend(SNAPSHOT_VERSION.to_le_bytes());
+    Writer { buf }
+  }
+
+  ///Write a section whose contents are produced by `f`.
+  fn section(&mut self, tag:[u8; 4], f:impl FnOnce(&mut Vec<u8>)) {
+    self.buf.extend(tag);
+    let start = self.buf.len();
+    self.buf.extend([0; 4]);
+    f(&mut self.buf);
+    let len = (self.buf.len() - start - 4) as u32;
+    self.buf[start..start + 4].copy_from_slice(&len.to_le_bytes());
+  }
+}
+
+fn put_words(buf:&mut Vec<u8>, words:&[u16]) {
+  buf.extend(words.iter().flat_map(|word| word.to_le_bytes()));
+}
+
+///Reads the fields of a section in order.
+struct Reader<'a> {
+  tag:[u8; 4],
+  bytes:&'a [u8]
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len:usize) -> Result<&'a [u8], VMErrors> {
+    if self.bytes.len() < len {
+      return Err(invalid(format!("section `{}` is truncated", String::from_utf8_lossy(&self.tag))));
+    }
+    let (taken, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  fn u8(&mut self) -> Result<u8, VMErrors> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<u32, VMErrors> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn u64(&mut self) -> Result<u64, VMErrors> {
+    Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+  }
+
+  fn words(&mut self, count:usize) -> Result<Vec<u16>, VMErrors> {
+    Ok(words(self.take(count * 2)?))
+  }
+}
+
+fn invalid(msg:String) -> VMErrors {
+  VMErrors::InvalidSnapshot(msg)
+}
+
+///A section's tag and contents.
+type Section<'a> = ([u8; 4], &'a [u8]);
+
+///Returns true if `bytes` start like a snapshot.
+pub fn is_snapshot(bytes:&[u8]) -> bool {
+  bytes.starts_with(MAGIC)
+}
+
+///Check a snapshot's header and split the rest into its tagged sections.
+fn sections(bytes:&[u8]) -> Result<Vec<Section<'_>>, VMErrors> {
+  let Some(header) = bytes.get(..8).filter(|header| header.starts_with(MAGIC))
+  else {
+    return Err(invalid(String::from("missing the snapshot header")));
+  };
+  let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+  if version != SNAPSHOT_VERSION {
+    return Err(invalid(format!("version {version} is not supported. Expected version {SNAPSHOT_VERSION}")));
+  }
+
+  let mut sections = Vec::new();
+  let mut r = Reader { tag:*MAGIC, bytes:&bytes[8..] };
+  while !r.bytes.is_empty() {
+    let tag:[u8; 4] = r.take(4)?.try_into().unwrap();
+    let len = r.u32()? as usize;
+    sections.push((tag, r.take(len)?));
+  }
+  Ok(sections)
+}
+
+///Append a section to the end of a snapshot. [`VM::from_snapshot`] skips
+/// sections it does not know so other data can be stored alongside the
+/// [`VM`].
+pub(crate) fn push_section(snapshot:&mut Vec<u8>, tag:[u8; 4], bytes:&[u8]) {
+  snapshot.extend(tag);
+  snapshot.extend((bytes.len() as u32).to_le_bytes());
+  snapshot.extend(bytes);
+}
+
+///Returns the contents of the section tagged `tag`, if the snapshot has one.
+pub(crate) fn find_section(snapshot:&[u8], tag:[u8; 4]) -> Result<Option<&[u8]>, VMErrors> {
+  Ok(sections(snapshot)?.into_iter().find(|(section_tag, _)| *section_tag == tag).map(|(_, section)| section))
+}
+
+///FNV-1a hash identifying the base image of a delta.
+fn hash(words:&[u16]) -> u64 {
+  words
+    .iter()
+    .flat_map(|word| word.to_le_bytes())
+    .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+///Returns the runs of `mem` which differ from `base`. Words past the end of
+/// `base` always differ.
+fn changed_runs(mem:&[u16], base:&[u16]) -> Vec<Range<usize>> {
+  let mut runs:Vec<Range<usize>> = Vec::new();
+  for (addr, word) in mem.iter().enumerate() {
+    if base.get(addr) == Some(word) {
+      continue;
+    }
+    match runs.last_mut() {
+      Some(run) if run.end == addr => run.end += 1,
+      _ => runs.push(addr..addr + 1)
+    }
+  }
+  runs
+}
+
+impl VM {
+  ///Encode the [`VM`] as a compact binary snapshot. If a `base` image is
+  /// given, memory is stored as the runs of words which differ from it and
+  /// the same image must be passed to [`VM::from_snapshot`].
+  pub fn to_snapshot(&self, base:Option<&[u16]>) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.section(REGS, |buf| put_words(buf, &self.reg));
+    w.section(CPU, |buf| {
+      buf.extend((self.pc as u32).to_le_bytes());
+      buf.push(self.running as u8);
+      buf.push(self.debug);
+      buf.extend(self.cycles.to_le_bytes());
+    });
+    w.section(STACK, |buf| put_words(buf, &self.stack));
+
+    match base {
+      Some(base) => w.section(MEM_DELTA, |buf| {
+        let runs = changed_runs(&self.mem, base);
+        buf.extend((self.mem.len() as u32).to_le_bytes());
+        buf.extend(hash(base).to_le_bytes());
+        buf.extend((runs.len() as u32).to_le_bytes());
+        for run in runs {
+          buf.extend((run.start as u32).to_le_bytes());
+          buf.extend((run.len() as u32).to_le_bytes());
+          put_words(buf, &self.mem[run]);
+        }
+      }),
+      None => w.section(MEM, |buf| put_words(buf, &self.mem))
+    }
+
+    if !self.inputs.is_empty() {
+      w.section(INPUT, |buf| buf.extend(&self.inputs));
+    }
+    if let Some(room) = &self.room {
+      w.section(ROOM, |buf| buf.extend(room.as_bytes()));
+    }
+
+    //The rarely used parts of the state are small so they are kept as JSON
+    if !self.patches.is_empty() {
+      w.section(PATCHES, |buf| serde_json::to_writer(buf, &self.patches).unwrap());
+    }
+    w.section(DEBUGGER, |buf| serde_json::to_writer(buf, &self.debugger).unwrap());
+    w.buf
+  }
+
+  ///Decode a snapshot written by [`VM::to_snapshot`]. The [`VM`] uses the
+  /// standard input and output. Unknown sections are skipped.
+  pub fn from_snapshot(bytes:&[u8], base:Option<&[u16]>) -> Result<VM, VMErrors> {
+    let mut vm = VM::new();
+    let (mut regs, mut cpu, mut mem) = (false, false, false);
+    for (tag, section) in sections(bytes)? {
+      let len = section.len();
+      let mut r = Reader { tag, bytes:section };
+
+      match tag {
+        REGS => {
+          vm.reg = r.words(8)?.try_into().unwrap();
+          regs = true;
+        }
+        CPU => {
+          vm.pc = r.u32()? as usize;
+          vm.running = r.u8()? != 0;
+          vm.debug = r.u8()?;
+          vm.cycles = r.u64()?;
+          cpu = true;
+        }
+        STACK => vm.stack = r.words(len / 2)?,
+        MEM => {
+          if len / 2 > WORDSIZE as usize {
+            return Err(invalid(format!("memory of {} words is larger than the address space", len / 2)));
+          }
+          vm.mem = r.words(len / 2)?;
+          mem = true;
+        }
+        MEM_DELTA => {
+          let Some(base) = base
+          else {
+            return Err(invalid(String::from("memory is stored as a delta but no base image was given")));
+          };
+          let mem_len = r.u32()? as usize;
+          if mem_len > WORDSIZE as usize {
+            return Err(invalid(format!("memory of {mem_len} words is larger than the address space")));
+          }
+          if r.u64()? != hash(base) {
+            return Err(invalid(String::from("the base image does not match the one the snapshot was taken against")));
+          }
+
+          vm.mem = base[..mem_len.min(base.len())].to_vec();
+          vm.mem.resize(mem_len, 0);
+          for _ in 0..r.u32()? {
+            let start = r.u32()? as usize;
+            let count = r.u32()? as usize;
+            let words = r.words(count)?;
+            let run = vm.mem.get_mut(start..start + count).ok_or_else(|| invalid(format!("memory run at {start} is out of bounds")))?;
+            run.copy_from_slice(&words);
+          }
+          mem = true;
+        }
+        INPUT => vm.inputs = VecDeque::from(section.to_vec()),
+        ROOM => vm.room = Some(String::from_utf8_lossy(section).into_owned()),
+        PATCHES => vm.patches = serde_json::from_slice::<Vec<Patch>>(section).map_err(|err| invalid(err.to_string()))?,
+        DEBUGGER => vm.debugger = serde_json::from_slice::<Debugger>(section).map_err(|err| invalid(err.to_string()))?,
+        _ => {}
+      }
+    }
+
+    match (regs, cpu, mem) {
+      (true, true, true) => Ok(vm),
+      _ => Err(invalid(String::from("missing the registers, cpu state or memory")))
+    }
+  }
+
+  ///Take a snapshot with memory stored as a delta against the loaded
+  /// program image.
+  pub fn snapshot(&self) -> Vec<u8> {
+    self.to_snapshot(Some(&self.image))
+  }
+
+  ///Restore a snapshot taken by [`VM::snapshot`] while keeping the [`VM`]'s
+  /// input, output and paths.
+  pub fn restore(&mut self, snapshot:&[u8]) -> Result<(), VMErrors> {
+    let vm = VM::from_snapshot(snapshot, Some(&self.image))?;
+    self.replace(vm);
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{errors::VMErrors, patch::Patch, vm::VM};
+
+  fn sample() -> VM {
+    let mut vm = VM::new();
+    vm.mem = (0..1000).collect();
+    vm.mem[10] = 5000;
+    vm.mem[11] = 5001;
+    vm.mem.push(7);
+    vm.reg = [1, 2, 3, 4, 5, 6, 7, 25734];
+    vm.stack = vec![9, 8];
+    vm.pc = 42;
+    vm.cycles = 123_456;
+    vm.room = Some(String::from("Foothills"));
+    vm.push_input(String::from("look\n"));
+    vm.patches.push(Patch::Register { reg:7, val:25734 });
+    vm.debugger.breakpoints.insert(42);
+    vm
+  }
+
+  fn assert_same(a:&VM, b:&VM) {
+    assert_eq!(a.reg, b.reg);
+    assert_eq!(a.mem, b.mem);
+    assert_eq!(a.stack, b.stack);
+    assert_eq!((a.pc, a.running, a.cycles), (b.pc, b.running, b.cycles));
+    assert_eq!(a.room, b.room);
+    assert_eq!(a.patches, b.patches);
+    assert_eq!(a.debugger.breakpoints, b.debugger.breakpoints);
+  }
+
+  #[test]
+  fn round_trip() {
+    let vm = sample();
+    let snapshot = vm.to_snapshot(None);
+    let loaded = VM::from_snapshot(&snapshot, None).unwrap();
+    assert_same(&vm, &loaded);
+
+    //Nothing else, like the pending input, is lost
+    assert_eq!(loaded.to_snapshot(None), snapshot);
+  }
+
+  #[test]
+  fn delta() {
+    let vm = sample();
+    let base = (0..1000).collect::<Vec<u16>>();
+    let snapshot = vm.to_snapshot(Some(&base));
+    assert!(snapshot.len() < vm.to_snapshot(None).len() / 4);
+    assert_same(&vm, &VM::from_snapshot(&snapshot, Some(&base)).unwrap());
+
+    //Deltas need the same base image
+    assert!(matches!(VM::from_snapshot(&snapshot, None), Err(VMErrors::InvalidSnapshot(_))));
+    assert!(matches!(VM::from_snapshot(&snapshot, Some(&base[1..])), Err(VMErrors::InvalidSnapshot(_))));
+  }
+
+  #[test]
+  fn restore() {
+    let mut vm = sample();
+    vm.image = (0..1000).collect();
+    let snapshot = vm.snapshot();
+
+    vm.mem[500] = 0;
+    vm.reg[0] = 99;
+    vm.restore(&snapshot).unwrap();
+    assert_same(&sample(), &vm);
+    assert_eq!(vm.image.len(), 1000);
+  }
+
+  #[test]
+  fn invalid() {
+    let snapshot = sample().to_snapshot(None);
+    assert!(VM::from_snapshot(b"JSON{}", None).is_err());
+    assert!(VM::from_snapshot(&snapshot[..snapshot.len() - 3], None).is_err());
+    assert!(VM::from_snapshot(&snapshot[..8], None).is_err());
+
+    //Memory cannot be larger than the address space
+    let mut vm = sample();
+    vm.mem.resize(40000, 0);
+    let base = vec![0; 10];
+    assert!(VM::from_snapshot(&vm.to_snapshot(None), None).is_err());
+    assert!(VM::from_snapshot(&vm.to_snapshot(Some(&base)), Some(&base)).is_err());
+  }
+}
@@ -0,0 +1,40 @@
+//!Small programs shared by the tests of several modules.
+use crate::{assembler::assemble, vm::VM};
+
+///Counts the characters entered in r2.
+pub(crate) fn counter() -> VM {
+  let mut vm = VM::new();
+  vm.mem = assemble(
+    "
+    prompt: out '>'
+    read:   in r0
+            eq r1 r0 '\\n'
+            jt r1 prompt
+            add r2 r2 1
+            jmp read
+    "
+  )
+  .unwrap();
+  vm.config.core = None;
+  vm
+}
+
+///Echoes each line until `q` is entered.
+pub(crate) fn echo() -> VM {
+  let mut vm = VM::new();
+  vm.mem = assemble(
+    "
+    prompt: out '>'
+    read:   in r0
+            eq r1 r0 'q'
+            jt r1 done
+            out r0
+            eq r1 r0 '\\n'
+            jt r1 prompt
+            jmp read
+    done:   halt
+    "
+  )
+  .unwrap();
+  vm
+}
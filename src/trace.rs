@@ -0,0 +1,287 @@
+use crate::{
+  errors::VMErrors,
+  instruction::{Instruction, Operand},
+  journal::Effect,
+  vm::{OpCall, OpCode, VM}
+};
+use std::{
+  collections::BTreeSet,
+  fmt,
+  fs::{File, OpenOptions},
+  io::{self, BufWriter, Write},
+  ops::RangeInclusive,
+  path::PathBuf
+};
+
+///Limits which executed instructions are written to the trace. An
+/// instruction is traced if it passes every filter which is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+  ///Only trace these opcodes. Empty traces every opcode.
+  pub ops:BTreeSet<OpCode>,
+  ///Only trace instructions at these addresses.
+  pub pcs:Option<RangeInclusive<usize>>,
+  ///Only trace while fewer than this many calls deep.
+  pub max_depth:Option<usize>,
+  ///Only trace inside the function entered at this address, including the
+  /// functions it calls.
+  pub function:Option<usize>
+}
+
+///A change to the [`TraceFilter`] made with `*trace`. Filters set without a
+/// value are removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceSetting {
+  Show,
+  Clear,
+  Ops(BTreeSet<OpCode>),
+  Pcs(Option<RangeInclusive<usize>>),
+  MaxDepth(Option<usize>),
+  Function(Option<usize>)
+}
+
+impl TraceFilter {
+  ///Returns true if the call stack an instruction runs in passes the filters.
+  /// `frames` holds the entry addresses of the functions being executed,
+  /// innermost last.
+  pub fn allows_frames(&self, frames:&[usize]) -> bool {
+    self.max_depth.is_none_or(|depth| frames.len() < depth) && self.function.is_none_or(|function| frames.contains(&function))
+  }
+
+  ///Returns true if the instruction at `pc` passes the filters.
+  pub fn allows(&self, op:OpCode, pc:usize) -> bool {
+    (self.ops.is_empty() || self.ops.contains(&op)) && self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&pc))
+  }
+}
+
+impl fmt::Display for TraceFilter {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    if *self == TraceFilter::default() {
+      return writeln!(f, "Tracing every instruction");
+    }
+    writeln!(f, "Tracing only:")?;
+    if !self.ops.is_empty() {
+      writeln!(f, "  opcodes {}", self.ops.iter().map(|op| format!("{op:?}")).collect::<Vec<String>>().join(" "))?;
+    }
+    if let Some(pcs) = &self.pcs {
+      writeln!(f, "  addresses {} to {}", pcs.start(), pcs.end())?;
+    }
+    if let Some(depth) = self.max_depth {
+      writeln!(f, "  less than {depth} calls deep")?;
+    }
+    if let Some(function) = self.function {
+      writeln!(f, "  inside the function at {function}")?;
+    }
+    Ok(())
+  }
+}
+
+impl VM {
+  ///Log every executed instruction to `path`, replacing what the file held.
+  pub fn trace_to<P:Into<PathBuf>>(&mut self, path:P) -> io::Result<()> {
+    self.config.trace = path.into();
+    File::create(&self.config.trace)?;
+    self.open_trace()
+  }
+
+  ///Open the trace file for appending.
+  fn open_trace(&mut self) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(&self.config.trace)?;
+    self.tracer = Some(BufWriter::new(file));
+    Ok(())
+  }
+
+  ///Turn tracing on or off. Tracing resumes at the end of the trace file.
+  pub(crate) fn toggle_trace(&mut self) {
+    match self.tracer.take() {
+      Some(tracer) => self.check_trace(tracer.into_inner().map(drop).map_err(|err| err.into_error())),
+      None => {
+        let opened = self.open_trace();
+        self.check_trace(opened)
+      }
+    }
+  }
+
+  ///Empty the trace file.
+  pub(crate) fn clear_trace(&mut self) {
+    self.flush_trace();
+    let cleared = File::create(&self.config.trace).map(drop);
+    self.check_trace(cleared);
+  }
+
+  ///Write any buffered trace lines to the trace file.
+  pub(crate) fn flush_trace(&mut self) {
+    if let Some(tracer) = &mut self.tracer {
+      let flushed = tracer.flush();
+      self.check_trace(flushed);
+    }
+  }
+
+  ///Stop tracing if the trace file could not be written.
+  fn check_trace(&mut self, written:io::Result<()>) {
+    if let Err(err) = written {
+      self.tracer = None;
+      self.output.write_str(&format!("Stopped tracing: {err}\n"));
+    }
+  }
+
+  ///Execute an [`Instruction`] and write it to the trace if tracing is on and
+  /// it passes the [`TraceFilter`]. Nothing is recorded while tracing is off.
+  //Inlined so untraced instructions do not pay for an extra call
+  #[inline(always)]
+  pub(crate) fn execute_traced(&mut self, inst:&Instruction) -> Result<(), VMErrors> {
+    match self.tracer {
+      Some(_) => self.execute_and_trace(inst),
+      None => self.execute(inst)
+    }
+  }
+
+  ///Execute an [`Instruction`] while tracing is on.
+  fn execute_and_trace(&mut self, inst:&Instruction) -> Result<(), VMErrors> {
+    //Filter on the calls the instruction runs in, not the ones it makes
+    if !self.trace_filter.allows(inst.op, inst.pc) || !self.trace_filter.allows_frames(&self.frames) {
+      return self.execute(inst);
+    }
+
+    let args = inst.operands().iter().map(|&arg| self.operand_value(arg)).collect();
+    self.execute(inst)?;
+    let call = self.new_opcall(inst, args);
+    if let Some(tracer) = &mut self.tracer {
+      let written = serde_json::to_writer(&mut *tracer, &call).map_err(io::Error::from).and_then(|()| writeln!(tracer));
+      self.check_trace(written);
+    }
+    Ok(())
+  }
+
+  ///Returns an operand's value without reporting the read to watchpoints.
+  fn operand_value(&self, arg:Operand) -> u16 {
+    match arg {
+      Operand::Register(reg) => self.reg[reg as usize],
+      arg => arg.raw()
+    }
+  }
+
+  ///Convert an executed [`Instruction`] into an [`OpCall`] holding the
+  /// changes it made.
+  fn new_opcall(&self, inst:&Instruction, args:Vec<u16>) -> OpCall {
+    let mut call = OpCall {
+      pc:inst.pc,
+      op:inst.op,
+      raw:inst.operands().iter().map(Operand::raw).collect(),
+      args,
+      regs:Vec::new(),
+      mem:Vec::new(),
+      pushed:Vec::new(),
+      popped:Vec::new()
+    };
+    for effect in self.history.last_effects() {
+      match effect {
+        Effect::Register(reg, _, val) => call.regs.push((reg, val)),
+        Effect::Memory(addr, _, val) => call.mem.push((addr, val)),
+        Effect::Push(val) => call.pushed.push(val),
+        Effect::Pop(val) => call.popped.push(val),
+        Effect::Input(..) | Effect::Enter | Effect::Leave(_) | Effect::Halt => {}
+      }
+    }
+    call
+  }
+
+  ///Change the [`TraceFilter`] and print the filters.
+  pub(crate) fn set_trace_filter(&mut self, setting:TraceSetting) {
+    let filter = &mut self.trace_filter;
+    match setting {
+      TraceSetting::Show => {}
+      TraceSetting::Clear => *filter = TraceFilter::default(),
+      TraceSetting::Ops(ops) => filter.ops = ops,
+      TraceSetting::Pcs(pcs) => filter.pcs = pcs,
+      TraceSetting::MaxDepth(depth) => filter.max_depth = depth,
+      TraceSetting::Function(function) => filter.function = function
+    }
+    let msg = self.trace_filter.to_string();
+    self.output.write_str(&msg);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{
+    assembler::assemble,
+    io::BufferOutput,
+    vm::{OpCall, VM}
+  };
+  use std::fs;
+
+  ///Returns the addresses traced after running the system commands.
+  fn traced(commands:&[&str]) -> Vec<usize> {
+    let path = std::env::temp_dir().join(format!("vm_trace_filter_test_{}.jsonl", commands.join("_").replace(['*', ' '], "")));
+
+    let mut vm = VM::new();
+    vm.mem = assemble(
+      "
+            call f
+            halt
+      f:    push r0
+            call g
+            pop r0
+            ret
+      g:    add r0 r0 1
+            ret
+      "
+    )
+    .unwrap();
+    vm.set_output(BufferOutput::new());
+    vm.trace_to(&path).unwrap();
+    for command in commands {
+      vm.exe_system_commands(command.to_string());
+    }
+    vm.dbg_run().unwrap();
+
+    let trace = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    trace.lines().map(|line| serde_json::from_str::<OpCall>(line).unwrap().pc).collect()
+  }
+
+  #[test]
+  fn filters() {
+    assert_eq!(traced(&[]), [0, 3, 5, 10, 14, 7, 9, 2]);
+    assert_eq!(traced(&["*trace ops call RET"]), [0, 5, 14, 9]);
+    assert_eq!(traced(&["*trace pc 3 9"]), [3, 5, 7, 9]);
+    assert_eq!(traced(&["*trace depth 1"]), [0, 2]);
+    assert_eq!(traced(&["*trace in 10"]), [10, 14]);
+    assert_eq!(traced(&["*trace in 3", "*trace ops ret"]), [14, 9]);
+    assert_eq!(traced(&["*trace in 3", "*trace depth 2", "*trace in"]), [0, 3, 5, 7, 9, 2]);
+    assert_eq!(traced(&["*trace depth 1", "*trace clear"]).len(), 8);
+  }
+
+  #[test]
+  fn toggle() {
+    let path = std::env::temp_dir().join("vm_trace_toggle_test.jsonl");
+
+    let mut vm = VM::new();
+    vm.mem = assemble("noop\nhalt").unwrap();
+    vm.set_output(BufferOutput::new());
+    vm.trace_to(&path).unwrap();
+    vm.exe_system_commands(String::from("*print"));
+    vm.step().unwrap();
+    vm.exe_system_commands(String::from("*print"));
+    vm.run().unwrap();
+
+    let trace = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(trace, "{\"pc\":1,\"op\":\"Halt\",\"raw\":[],\"args\":[]}\n");
+  }
+
+  #[test]
+  fn show() {
+    let mut vm = VM::new();
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    vm.exe_system_commands(String::from("*trace ops call ret"));
+    vm.exe_system_commands(String::from("*trace pc 100 200"));
+    vm.exe_system_commands(String::from("*trace clear"));
+    assert_eq!(
+      out.contents(),
+      "Tracing only:\n  opcodes Call Ret\nTracing only:\n  opcodes Call Ret\n  addresses 100 to 200\nTracing every instruction\n"
+    );
+  }
+}
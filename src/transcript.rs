@@ -0,0 +1,225 @@
+use crate::{
+  commands::Command,
+  errors::VMErrors,
+  vm::{RunOutcome, RunStatus, VM}
+};
+use std::{
+  fmt,
+  fs::File,
+  io::{self, LineWriter, Write},
+  path::Path
+};
+
+///Records everything fed to a [`VM`]'s pending input and optionally
+/// everything it prints. Lines from the [`Input`](crate::io::Input),
+/// [`VM::push_input`] and the solvers are all recorded. System commands which
+/// change the game, like `*undo`, are recorded so replays repeat them. Other
+/// system commands are not since the lines they feed the game are recorded
+/// instead.
+pub struct Transcript {
+  input:LineWriter<File>,
+  output:Option<LineWriter<File>>
+}
+
+impl fmt::Debug for Transcript {
+  fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Transcript").field("output", &self.output.is_some()).finish()
+  }
+}
+
+impl Transcript {
+  ///Record the input to a new file at `path`.
+  pub fn create<P:AsRef<Path>>(path:P) -> io::Result<Self> {
+    Ok(Transcript {
+      input:LineWriter::new(File::create(path)?),
+      output:None
+    })
+  }
+
+  ///Also record the output to a new file at `path`.
+  pub fn with_output<P:AsRef<Path>>(mut self, path:P) -> io::Result<Self> {
+    self.output = Some(LineWriter::new(File::create(path)?));
+    Ok(self)
+  }
+}
+
+impl VM {
+  ///Start recording a [`Transcript`].
+  pub fn record_transcript(&mut self, transcript:Transcript) {
+    self.transcript = Some(transcript);
+  }
+
+  ///Add text to the [`Transcript`]'s input.
+  pub(crate) fn transcribe_input(&mut self, bytes:&[u8]) {
+    if let Some(transcript) = &mut self.transcript {
+      let written = transcript.input.write_all(bytes);
+      self.check_transcript(written);
+    }
+  }
+
+  ///Add a printed character to the [`Transcript`]'s output.
+  pub(crate) fn transcribe_output(&mut self, c:char) {
+    if let Some(output) = self.transcript.as_mut().and_then(|transcript| transcript.output.as_mut()) {
+      let written = output.write_all(&[c as u8]);
+      self.check_transcript(written);
+    }
+  }
+
+  ///Record a system command which changes the game so replays repeat it at
+  /// the same prompt. Errors for commands a replay cannot repeat, like loading
+  /// a save which may have changed since.
+  pub(crate) fn transcribe_command(&mut self, line:&str, cmd:&Command) -> Result<(), VMErrors> {
+    if self.transcript.is_none() {
+      return Ok(());
+    }
+    let line = line.trim();
+    let replayed = match cmd {
+      Command::Load(_) | Command::StepBack(_) | Command::ReverseContinue => return Err(VMErrors::Unreplayable(line.to_string())),
+      Command::Reg { val, .. } => val.is_some(),
+      Command::Rewind(_) | Command::RageQuit | Command::Poke { .. } => true,
+      _ => false
+    };
+    if replayed {
+      let line = format!("*{}\n", line.strip_prefix('*').unwrap_or(line));
+      self.transcribe_input(line.as_bytes());
+    }
+    Ok(())
+  }
+
+  ///Stop recording if the [`Transcript`] could not be written.
+  fn check_transcript(&mut self, written:io::Result<()>) {
+    if let Err(err) = written {
+      self.transcript = None;
+      self.output.write_str(&format!("Stopped recording the transcript: {err}\n"));
+    }
+  }
+
+  ///Feed a recorded transcript to the [`VM`] and run until it is used up.
+  /// Recorded system commands run once the [`VM`] reaches the prompt they
+  /// were entered at. Never reads from the [`VM`]'s
+  /// [`Input`](crate::io::Input) so replays are deterministic. The outcome
+  /// holds everything the game printed.
+  pub fn replay(&mut self, transcript:&str, mut max_cycles:usize) -> RunOutcome {
+    self.captured = Some(String::new());
+    let mut stopped = None;
+    for line in transcript.split_inclusive('\n') {
+      if !line.starts_with('*') {
+        self.push_input(line.to_string());
+        continue;
+      }
+      match self.run_to_prompt(&mut max_cycles) {
+        RunStatus::WaitingForInput => self.exe_system_commands(line.to_string()),
+        status => {
+          stopped = Some(status);
+          break;
+        }
+      }
+    }
+
+    let status = stopped.unwrap_or_else(|| self.run_to_prompt(&mut max_cycles));
+    self.flush_trace();
+    let output = self.captured.take().unwrap_or_default();
+    RunOutcome { status, output }
+  }
+}
+
+///Compare the output of a replay against a recorded output log. Errors with
+/// the first line which differs.
+pub fn verify_output(output:&str, expected:&str) -> Result<(), VMErrors> {
+  let mut found = output.split_inclusive('\n');
+  let mut lines = expected.split_inclusive('\n');
+  let mut line = 1;
+  loop {
+    match (lines.next(), found.next()) {
+      (None, None) => return Ok(()),
+      (Some(expected), Some(found)) if expected == found => line += 1,
+      (expected, found) => {
+        return Err(VMErrors::ReplayMismatch {
+          line,
+          expected:expected.unwrap_or_default().trim_end().to_string(),
+          found:found.unwrap_or_default().trim_end().to_string()
+        })
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{verify_output, Transcript};
+  use crate::{
+    errors::VMErrors,
+    io::{BufferInput, BufferOutput},
+    test_programs::{counter, echo},
+    vm::RunStatus
+  };
+  use std::fs;
+
+  #[test]
+  fn record_and_replay() {
+    let dir = std::env::temp_dir();
+    let (input, output) = (dir.join("vm_transcript_test.txt"), dir.join("vm_transcript_test.out"));
+
+    let mut vm = echo();
+    vm.set_input(BufferInput::new(["look", "*help", "q"]));
+    vm.set_output(BufferOutput::new());
+    vm.record_transcript(Transcript::create(&input).unwrap().with_output(&output).unwrap());
+    vm.push_input(String::from("hi\n"));
+    vm.run().unwrap();
+    drop(vm);
+
+    //System commands are left out
+    let transcript = fs::read_to_string(&input).unwrap();
+    let log = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(transcript, "hi\nlook\nq\n");
+    assert_eq!(log, ">hi\n>look\n>");
+
+    //Replaying never reads the input
+    let mut vm = echo();
+    vm.set_input(BufferInput::new(["unused"]));
+    let outcome = vm.replay(&transcript, 10_000);
+    assert!(matches!(outcome.status, RunStatus::Halted));
+    assert!(verify_output(&outcome.output, &log).is_ok());
+  }
+
+  #[test]
+  fn replay_undo() {
+    let path = std::env::temp_dir().join("vm_transcript_undo_test.txt");
+
+    let mut vm = counter();
+    vm.set_input(BufferInput::new(["ab", "*undo", "*reg 3 7", "c", "*load", "d"]));
+    vm.set_output(BufferOutput::new());
+    vm.record_transcript(Transcript::create(&path).unwrap());
+    assert_eq!(vm.run().unwrap_err().kind, VMErrors::InputEof);
+    drop(vm.transcript.take());
+
+    //Commands which change the game are recorded and the rest refused
+    let transcript = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(transcript, "ab\n*undo\n*reg 3 7\nc\nd\n");
+    assert_eq!((vm.reg[2], vm.reg[3]), (2, 7));
+
+    let mut replayed = counter();
+    let outcome = replayed.replay(&transcript, 10_000);
+    assert!(matches!(outcome.status, RunStatus::WaitingForInput));
+    assert_eq!(outcome.output, ">>>>");
+    assert_eq!((replayed.reg, replayed.pc, &replayed.mem), (vm.reg, vm.pc, &vm.mem));
+  }
+
+  #[test]
+  fn mismatch() {
+    assert!(verify_output("a\nb\n", "a\nb\n").is_ok());
+    let err = verify_output("a\nc\nd\n", "a\nb\n").unwrap_err();
+    assert_eq!(
+      err,
+      VMErrors::ReplayMismatch {
+        line:2,
+        expected:String::from("b"),
+        found:String::from("c")
+      }
+    );
+    assert!(matches!(verify_output("a\n", "a\nb\n"), Err(VMErrors::ReplayMismatch { line:2, .. })));
+  }
+}
@@ -1,16 +1,28 @@
 use crate::{
-  errors::VMErrors,
-  helpers::{
-    solver, Graph,
-    Operation::{Add, Mul, Sub}
-  }
+  commands::{help, Command},
+  config::VmConfig,
+  debugger::{Access, Debugger, Location},
+  disassembler::{disassemble, words},
+  errors::{Fault, VMErrors, FAULT_STACK_LEN},
+  helpers::{solver, vault},
+  history::History,
+  instruction::{decode, Instruction, Operand},
+  io::{self, Input, Output},
+  journal::{Effect, Journal},
+  patch::Patch,
+  profile::Profiler,
+  save::{find_slot, fmt_timestamp, list_saves, read_save, write_slot},
+  trace::TraceFilter,
+  transcript::Transcript
 };
-use eyre::Result;
+use eyre::{Result, WrapErr};
 use serde::{Deserialize, Serialize};
 use std::{
   collections::VecDeque,
   fs::{self, File},
-  io::{stdin, Write}
+  io::{BufWriter, Write},
+  path::Path,
+  sync::Arc
 };
 
 pub const WORDSIZE:u16 = 32768;
@@ -40,70 +52,39 @@ pub enum OpCode {
   In = 20,
   Noop = 21
 }
+
+///An executed [`Instruction`] and the changes it made. Traced as a line of
+/// JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OpCall {
-  code:OpCode,
-  pc:usize,
-  args:Vec<u16>,
-  reg:[u16; 8],
-  stack_size:usize,
-  stack:Vec<u16>
+  pub pc:usize,
+  pub op:OpCode,
+  ///The operands as stored in memory.
+  pub raw:Vec<u16>,
+  ///The operands' values. Registers are read before the instruction runs.
+  pub args:Vec<u16>,
+  ///The registers written and their new values.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub regs:Vec<(u8, u16)>,
+  ///The memory addresses written and their new values.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub mem:Vec<(u16, u16)>,
+  ///The values pushed onto the stack.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub pushed:Vec<u16>,
+  ///The values popped off the stack.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub popped:Vec<u16>
 }
 
-impl OpCall {
-  ///Create a new [`OpCall`].
-  pub fn new(code:OpCode, pc:usize, args:Vec<u16>, reg:[u16; 8], stack_size:usize, stack:Vec<u16>) -> Self {
-    OpCall {
-      code,
-      pc,
-      args,
-      reg,
-      stack_size,
-      stack
-    }
-  }
-
-  ///Convert an [`OpCall`] into its [`String`] representation.
-  pub fn to_string(&self) -> String {
-    let code = serde_json::to_string(&self.code).unwrap();
-    let args = format!("{:?}", self.args);
-    let reg = format!(
-      "R0:{:#?},R1:{:#?},R2:{:#?},R3:{:#?},R4:{:#?},R5:{:#?},R6:{:#?},R7:{:#?}",
-      self.reg[0], self.reg[1], self.reg[2], self.reg[3], self.reg[4], self.reg[5], self.reg[6], self.reg[7]
-    );
-    let stack_size = format!("Len: {}", self.stack_size);
-
-    let stack;
-    if self.stack.len() == 0 {
-      stack = format!("Stack Contents: {}", "Empty");
-    }
-    else if self.stack.len() > 15 {
-      let mut items = String::new();
-      for i in &self.stack[self.stack.len() - 16..] {
-        items += &i.to_string();
-        items += ", ";
-      }
-      stack = format!("Stack Contents(Abbreviated): {items}");
-    }
-    else {
-      let mut items = String::new();
-      for i in &self.stack {
-        items += &i.to_string();
-        items += ", ";
-      }
-      stack = format!("Stack Contents: {items}");
-    }
-
-    let call = format!(
-      "Code: {}",
-      code + "\n\t" + "Pc: " + &self.pc.to_string() + "\n\t" + "Args: " + &args + "\n\t" + "Regs: " + &reg + "\n\t" + &stack_size + "\n\t" + &stack + "\n"
-    );
-    String::from(call)
+impl OpCode {
+  ///Look up an [`OpCode`] by its case-insensitive name.
+  pub fn from_name(name:&str) -> Option<OpCode> {
+    (0..=21).filter_map(|code| OpCode::new(code).ok()).find(|op| format!("{op:?}").eq_ignore_ascii_case(name))
   }
-}
 
-impl OpCode {
   ///Create a new [`OpCode`] from a u16.
-  pub fn new(value:u16) -> Result<OpCode> {
+  pub fn new(value:u16) -> Result<OpCode, VMErrors> {
     match value {
       0 => Ok(OpCode::Halt),
       1 => Ok(OpCode::Set),
@@ -127,7 +108,17 @@ impl OpCode {
       19 => Ok(OpCode::Out),
       20 => Ok(OpCode::In),
       21 => Ok(OpCode::Noop),
-      _ => return Err(VMErrors::UnknownOpcode(value).into())
+      _ => Err(VMErrors::UnknownOpcode(value))
+    }
+  }
+
+  ///Returns the number of [`Operand`]s the [`OpCode`] takes.
+  pub fn arity(&self) -> usize {
+    match self {
+      OpCode::Halt | OpCode::Ret | OpCode::Noop => 0,
+      OpCode::Push | OpCode::Pop | OpCode::Jmp | OpCode::Call | OpCode::Out | OpCode::In => 1,
+      OpCode::Set | OpCode::Jt | OpCode::Jf | OpCode::Not | OpCode::Rmem | OpCode::Wmem => 2,
+      OpCode::Eq | OpCode::Gt | OpCode::Add | OpCode::Mult | OpCode::Mod | OpCode::And | OpCode::Or => 3
     }
   }
 }
@@ -145,13 +136,113 @@ pub struct VM {
   /// should terminate.
   pub running:bool,
   ///Stores text inputs
-  inputs:VecDeque<u8>,
-  debug:u8
+  pub(crate) inputs:VecDeque<u8>,
+  pub(crate) debug:u8,
+  ///Number of instructions executed.
+  #[serde(default)]
+  pub cycles:u64,
+  ///Title of the last room the game printed, like `Foothills` for
+  /// `== Foothills ==`.
+  #[serde(default)]
+  pub room:Option<String>,
+  ///The line the `Out` opcode is printing, used to find room titles.
+  #[serde(skip)]
+  line:String,
+  ///Register and memory changes applied by system commands.
+  #[serde(default)]
+  pub patches:Vec<Patch>,
+  ///Breakpoints used by [`VM::dbg_run`].
+  #[serde(default)]
+  pub debugger:Debugger,
+  ///Where the `In` opcode reads lines from once `inputs` is empty.
+  #[serde(skip, default = "io::default_input")]
+  pub(crate) input:Box<dyn Input>,
+  ///Where the `Out` opcode prints to.
+  #[serde(skip, default = "io::default_output")]
+  pub(crate) output:Box<dyn Output>,
+  ///Collects the `Out` opcode's text instead of the [`Output`] while
+  /// [`VM::run_until_input`] or [`VM::replay`] is running.
+  #[serde(skip)]
+  pub(crate) captured:Option<String>,
+  ///Set while the `In` opcode reads a line from the [`Input`].
+  #[serde(skip)]
+  pub(crate) reading:bool,
+  ///Set once the `In` opcode has read part of a line.
+  #[serde(skip)]
+  pub(crate) mid_line:bool,
+  ///Entry addresses of the functions being executed, innermost last. Only
+  /// holds the calls made since the [`VM`] was started or loaded.
+  #[serde(skip)]
+  pub(crate) frames:Vec<usize>,
+  ///Where executed instructions are logged while tracing.
+  #[serde(skip)]
+  pub(crate) tracer:Option<BufWriter<File>>,
+  ///Which instructions are written to the trace.
+  #[serde(skip)]
+  pub trace_filter:TraceFilter,
+  ///Counts the executed instructions while profiling.
+  #[serde(skip)]
+  pub profiler:Option<Profiler>,
+  ///Reverse changes of the executed instructions while [`VM::dbg_run`] runs.
+  #[serde(skip)]
+  pub(crate) journal:Option<Journal>,
+  ///Snapshots taken at the start of each command for `*undo`, oldest first.
+  #[serde(skip)]
+  pub(crate) checkpoints:VecDeque<Vec<u8>>,
+  ///The recently executed instructions and their effects.
+  #[serde(skip)]
+  pub(crate) history:History,
+  ///The program binary as it was loaded, used as the base of snapshots.
+  #[serde(skip)]
+  pub(crate) image:Arc<[u16]>,
+  ///Records the input and output while set.
+  #[serde(skip)]
+  pub(crate) transcript:Option<Transcript>,
+  ///The files the [`VM`] reads and writes.
+  #[serde(skip)]
+  pub config:VmConfig,
+  ///Number of instructions after which the [`VM`] stops with
+  /// [`VMErrors::CycleLimit`]. [`None`] runs without a limit.
+  #[serde(skip)]
+  pub cycle_limit:Option<u64>,
+  ///Number of instructions counted against the [`VM::cycle_limit`]. Unlike
+  /// `cycles` it is not restored from saves.
+  #[serde(skip)]
+  pub(crate) limit_cycles:u64
+}
+
+///Why [`VM::run_until_input`] handed control back to the caller.
+#[derive(Debug)]
+pub enum RunStatus {
+  ///The `Halt` opcode was executed.
+  Halted,
+  ///An instruction returned an error.
+  Faulted(Box<Fault>),
+  ///The `In` opcode needs a new line. Add one with [`VM::push_input`].
+  WaitingForInput,
+  ///The cycle budget was used up before the [`VM`] halted or needed input.
+  OutOfCycles
+}
+
+///The result of [`VM::run_until_input`].
+#[derive(Debug)]
+pub struct RunOutcome {
+  pub status:RunStatus,
+  ///The text printed since the last call.
+  pub output:String
 }
 
+///Name of the save used when a save command is not given one.
+const SAVE:&str = "sync_save";
+
 //Debug Bitflags
 const DEBUG:u8 = 1 << 7;
-const PRINT:u8 = 1 << 5;
+
+impl Default for VM {
+  fn default() -> Self {
+    VM::new()
+  }
+}
 
 impl VM {
   pub fn new() -> Self {
@@ -162,123 +253,361 @@ impl VM {
       pc:0,
       running:true,
       inputs:VecDeque::new(),
-      debug:0
+      debug:0,
+      cycles:0,
+      room:None,
+      line:String::new(),
+      patches:Vec::new(),
+      debugger:Debugger::default(),
+      input:io::default_input(),
+      output:io::default_output(),
+      captured:None,
+      reading:false,
+      mid_line:false,
+      checkpoints:VecDeque::new(),
+      frames:Vec::new(),
+      tracer:None,
+      trace_filter:TraceFilter::default(),
+      profiler:None,
+      journal:None,
+      history:History::default(),
+      image:Arc::from([]),
+      transcript:None,
+      config:VmConfig::default(),
+      cycle_limit:None,
+      limit_cycles:0
     }
   }
 
-  pub fn run(&mut self) -> Result<()> {
-    while self.running {
-      let op = OpCode::new(self.mem[self.pc])?;
-      self.execute(op)?;
+  ///Create a new [`VM`] which reads from `input` and prints to `output`
+  /// instead of the standard input and output.
+  pub fn with_io<I:Input + 'static, O:Output + 'static>(input:I, output:O) -> Self {
+    let mut vm = VM::new();
+    vm.set_input(input);
+    vm.set_output(output);
+    vm
+  }
+
+  ///Replace the source the `In` opcode reads lines from.
+  pub fn set_input<I:Input + 'static>(&mut self, input:I) {
+    self.input = Box::new(input);
+  }
+
+  ///Replace the destination the `Out` opcode prints to.
+  pub fn set_output<O:Output + 'static>(&mut self, output:O) {
+    self.output = Box::new(output);
+  }
+
+  ///Replace the [`VM`] with a fresh one while keeping its input and output.
+  fn reset(&mut self) {
+    self.replace(VM::new());
+  }
+
+  ///Replace the [`VM`]'s state with another [`VM`]'s while keeping its input,
+  /// output, paths, program image, checkpoints and transcript.
+  pub(crate) fn replace(&mut self, mut new:VM) {
+    std::mem::swap(&mut new.input, &mut self.input);
+    std::mem::swap(&mut new.output, &mut self.output);
+    std::mem::swap(&mut new.config, &mut self.config);
+    std::mem::swap(&mut new.tracer, &mut self.tracer);
+    std::mem::swap(&mut new.trace_filter, &mut self.trace_filter);
+    std::mem::swap(&mut new.profiler, &mut self.profiler);
+    new.cycle_limit = self.cycle_limit;
+    new.limit_cycles = self.limit_cycles;
+    std::mem::swap(&mut new.image, &mut self.image);
+    std::mem::swap(&mut new.checkpoints, &mut self.checkpoints);
+    std::mem::swap(&mut new.transcript, &mut self.transcript);
+    std::mem::swap(&mut new.captured, &mut self.captured);
+    //The journal cannot reverse a change of the whole state
+    new.journal = self.journal.take().map(|_| Journal::default());
+    *self = new;
+  }
+
+  ///Run until the [`VM`] halts. Returns a [`Fault`] describing the
+  /// instruction which failed if the program errors. Continues under
+  /// [`VM::dbg_run`] once a break [`Watchpoint`](crate::debugger::Watchpoint)
+  /// triggers.
+  pub fn run(&mut self) -> Result<(), Box<Fault>> {
+    let result = (|| {
+      while self.running {
+        //A break watchpoint hands the rest of the run to the debugger
+        if self.debugger.paused() {
+          return self.dbg_run();
+        }
+        self.step()?;
+      }
+      Ok(())
+    })();
+    self.flush_trace();
+    result
+  }
+
+  ///Decode and execute the [`Instruction`] at the program counter. Writes a
+  /// core dump if the [`Instruction`] faults.
+  pub fn step(&mut self) -> Result<(), Box<Fault>> {
+    let fault = match decode(&self.mem, self.pc) {
+      Ok(inst) => match self.execute_traced(&inst) {
+        Ok(()) => return Ok(()),
+        Err(kind) => {
+          //Point the program counter back at the faulting instruction
+          self.pc = inst.pc;
+          self.fault(kind, Some(inst))
+        }
+      },
+      Err(kind) => self.fault(kind, None)
+    };
+    //Reaching the cycle limit is requested rather than a crash
+    if !matches!(fault.kind, VMErrors::CycleLimit(_)) {
+      self.dump_core(&fault);
     }
-    Ok(())
+    Err(fault)
   }
 
-  pub fn dbg_run(&mut self) -> Result<()> {
-    self.debug();
+  ///Record the [`VM`]'s state when an error stops it. `inst` is the
+  /// [`Instruction`] which failed, or [`None`] if it could not be decoded.
+  pub fn fault(&self, kind:VMErrors, inst:Option<Instruction>) -> Box<Fault> {
+    let start = self.stack.len().saturating_sub(FAULT_STACK_LEN);
+    Box::new(Fault {
+      kind,
+      pc:inst.map_or(self.pc, |inst| inst.pc),
+      instruction:inst,
+      reg:self.reg,
+      stack_len:self.stack.len(),
+      stack_top:self.stack[start..].to_vec()
+    })
+  }
 
-    let mut file = fs::File::create("debug_log.txt").unwrap();
+  ///Run until the [`VM`] halts, faults, executes at most `max_cycles`
+  /// instructions, or reaches an `In` opcode with no pending input. Unlike
+  /// [`VM::run`], this never reads from the [`VM`]'s [`Input`] and the
+  /// printed text is returned instead of written to its [`Output`].
+  pub fn run_until_input(&mut self, mut max_cycles:usize) -> RunOutcome {
+    self.captured = Some(String::new());
+    let status = self.run_to_prompt(&mut max_cycles);
+    self.flush_trace();
+    let output = self.captured.take().unwrap_or_default();
+    RunOutcome { status, output }
+  }
 
-    while self.running {
-      let op = OpCode::new(self.mem[self.pc])?;
+  ///Run until the [`VM`] needs a line of input, spending at most `cycles`
+  /// instructions and taking the ones executed from it.
+  pub(crate) fn run_to_prompt(&mut self, cycles:&mut usize) -> RunStatus {
+    loop {
+      if !self.running {
+        break RunStatus::Halted;
+      }
+      if *cycles == 0 {
+        break RunStatus::OutOfCycles;
+      }
 
-      let call = self.execute(op)?;
+      let inst = match decode(&self.mem, self.pc) {
+        Ok(inst) => inst,
+        Err(kind) => break RunStatus::Faulted(self.fault(kind, None))
+      };
+
+      //Stop before the In opcode would block waiting for a line
+      if inst.op == OpCode::In && self.inputs.is_empty() {
+        break RunStatus::WaitingForInput;
+      }
 
-      if self.debug & PRINT > 0 {
-        self.debug_print(&mut file, call)
+      if let Err(kind) = self.execute_traced(&inst) {
+        self.pc = inst.pc;
+        break RunStatus::Faulted(self.fault(kind, Some(inst)));
       }
+      *cycles -= 1;
     }
+  }
 
-    Ok(())
+  ///Run with debugging enabled. Stops at the [`Debugger`]'s breakpoints and
+  /// prompts for debugger commands.
+  pub fn dbg_run(&mut self) -> Result<(), Box<Fault>> {
+    self.debug |= DEBUG;
+    self.journal.get_or_insert_with(Journal::default);
+
+    let result = (|| {
+      while self.running {
+        if self.debugger.should_break(self.pc) {
+          self.debug_prompt();
+          if !self.running {
+            break;
+          }
+        }
+        self.step()?;
+      }
+      Ok(())
+    })();
+    self.flush_trace();
+    result
   }
 
-  pub fn execute(&mut self, op:OpCode) -> Result<OpCall> {
-    match op {
+  ///Execute a decoded [`Instruction`]. The program counter is moved past the
+  /// [`Instruction`] before its [`OpCode`] runs so jumps can overwrite it.
+  pub fn execute(&mut self, inst:&Instruction) -> Result<(), VMErrors> {
+    if let Some(limit) = self.cycle_limit {
+      if self.limit_cycles >= limit {
+        return Err(VMErrors::CycleLimit(limit));
+      }
+      self.limit_cycles += 1;
+    }
+    self.history.record(*inst);
+    self.cycles += 1;
+    if let Some(journal) = &mut self.journal {
+      journal.begin(inst.pc);
+    }
+    if let Some(profiler) = &mut self.profiler {
+      profiler.count(inst.pc, inst.op, self.frames.last().copied());
+    }
+    self.pc = inst.next_pc();
+
+    let args = inst.operands();
+    match inst.op {
       OpCode::Halt => self.Halt(),
-      OpCode::Set => self.Set(),
-      OpCode::Push => self.Push(),
-      OpCode::Pop => self.Pop(),
-      OpCode::Eq => self.Eq(),
-      OpCode::Gt => self.Gt(),
-      OpCode::Jmp => self.Jmp(),
-      OpCode::Jt => self.Jt(),
-      OpCode::Jf => self.Jf(),
-      OpCode::Add => self.Add(),
-      OpCode::Mult => self.Mult(),
-      OpCode::Mod => self.Mod(),
-      OpCode::And => self.And(),
-      OpCode::Or => self.Or(),
-      OpCode::Not => self.Not(),
-      OpCode::Rmem => self.Rmem(),
-      OpCode::Wmem => self.Wmem(),
-      OpCode::Call => self.Call(),
+      OpCode::Set => self.Set(args),
+      OpCode::Push => self.Push(args),
+      OpCode::Pop => self.Pop(args),
+      OpCode::Eq => self.Eq(args),
+      OpCode::Gt => self.Gt(args),
+      OpCode::Jmp => self.Jmp(args),
+      OpCode::Jt => self.Jt(args),
+      OpCode::Jf => self.Jf(args),
+      OpCode::Add => self.Add(args),
+      OpCode::Mult => self.Mult(args),
+      OpCode::Mod => self.Mod(args),
+      OpCode::And => self.And(args),
+      OpCode::Or => self.Or(args),
+      OpCode::Not => self.Not(args),
+      OpCode::Rmem => self.Rmem(args),
+      OpCode::Wmem => self.Wmem(args),
+      OpCode::Call => self.Call(args),
       OpCode::Ret => self.Ret(),
-      OpCode::Out => self.Out(),
-      OpCode::In => self.In(),
+      OpCode::Out => self.Out(args),
+      OpCode::In => self.In(args),
       OpCode::Noop => self.Noop()
+    }?;
+
+    if !self.debugger.watchpoints.is_empty() {
+      self.report_watch_hits(inst);
     }
+
+    Ok(())
   }
 
-  ///Returns the requested number of arguments and increments the program
-  /// counter by the requested number of arguments.
-  pub fn get_args(&mut self, num:usize) -> Vec<u16> {
-    //Increment the program counter to the first argument
-    self.pc += 1;
+  ///Tests whether an argument is a register or a literal. If the argument is
+  /// a register, return the value of the register. Otherwise return the
+  /// argument.
+  pub fn get_register_value(&mut self, arg:Operand) -> u16 {
+    match arg {
+      Operand::Literal(val) => val,
+      Operand::Register(reg) => {
+        let val = self.reg[reg as usize];
+        self.watch(Location::Register(reg), Access::Read, val);
+        val
+      }
+      //The decoder rejects invalid operands
+      Operand::Invalid(val) => unreachable!("invalid operand `{val}` was not rejected by the decoder")
+    }
+  }
 
-    //Get the arguments
-    let args = Vec::from(&self.mem[self.pc..self.pc + num]);
+  ///Write a value into a register.
+  #[inline]
+  fn set_register(&mut self, reg:usize, val:u16) {
+    self.journal(Effect::Register(reg as u8, self.reg[reg], val));
+    self.reg[reg] = val;
+    self.watch(Location::Register(reg as u8), Access::Write, val);
+  }
 
-    //Update the PC
-    self.pc += num;
+  ///Read a value from memory.
+  fn read_mem(&mut self, addr:u16) -> Result<u16, VMErrors> {
+    let Some(&val) = self.mem.get(addr as usize)
+    else {
+      return Err(VMErrors::OutOfBounds(addr as usize));
+    };
+    self.watch(Location::Memory(addr), Access::Read, val);
+    Ok(val)
+  }
 
-    //Return the aruments
-    args
+  ///Write a value into memory.
+  fn write_mem(&mut self, addr:u16, val:u16) -> Result<(), VMErrors> {
+    let Some(&old) = self.mem.get(addr as usize)
+    else {
+      return Err(VMErrors::OutOfBounds(addr as usize));
+    };
+    self.journal(Effect::Memory(addr, old, val));
+    self.mem[addr as usize] = val;
+    self.watch(Location::Memory(addr), Access::Write, val);
+    Ok(())
   }
 
-  ///Tests whether an argument is a register address or a value. If the
-  /// argument is a register, return the value of the register.
-  /// Otherwise return the argument.
-  pub fn get_register_value(&self, test_arg:u16) -> u16 {
-    let mut arg = test_arg;
+  fn push_stack(&mut self, val:u16) {
+    self.journal(Effect::Push(val));
+    self.stack.push(val);
+  }
 
-    //If the argument >= 32768, it is a register
-    if test_arg >= WORDSIZE {
-      //Read the value from the register and return it as the argument
-      let addr = (test_arg % WORDSIZE) as usize;
-      arg = self.reg[addr];
+  fn pop_stack(&mut self) -> Result<u16, VMErrors> {
+    let Some(val) = self.stack.pop()
+    else {
+      return Err(VMErrors::EmptyStack);
+    };
+    self.journal(Effect::Pop(val));
+    Ok(val)
+  }
+
+  ///Pass an access to the [`Debugger`]'s watchpoints.
+  fn watch(&mut self, location:Location, access:Access, val:u16) {
+    if !self.debugger.watchpoints.is_empty() {
+      self.debugger.watch(location, access, val);
     }
-    //Otherwise the argument is equal to the test_argument
-    arg
   }
 
-  fn read_input(&mut self) {
-    let mut s = String::new();
-    stdin().read_line(&mut s).unwrap();
+  ///Returns the index of the register an argument indicates. Errors if the
+  /// argument is not a register.
+  fn get_register(&self, arg:Operand) -> Result<usize, VMErrors> {
+    match arg {
+      Operand::Register(reg) => Ok(reg as usize),
+      arg => Err(VMErrors::InvalidRegister(arg.raw()))
+    }
+  }
+
+  ///Reads lines from the [`VM`]'s [`Input`] until one which is not a system
+  /// command is found. Returns `false` if the [`Input`] is exhausted.
+  fn read_input(&mut self) -> bool {
+    while let Some(mut s) = self.input.read_line() {
+      if s.starts_with('*') {
+        self.exe_system_commands(s);
+
+        //Stop reading if the system command halted the VM or replaced the
+        //state the `In` was reading for
+        if !self.running || !self.reading {
+          return false;
+        }
+        continue;
+      }
 
-    if '*' == s.chars().nth(0).unwrap() {
-      self.exe_system_commands(s);
-      self.read_input();
+      s.retain(|c| c != '\r');
+      self.transcribe_input(s.as_bytes());
+      self.inputs.extend(s.as_bytes());
+      return true;
+    }
+    false
+  }
+
+  ///Watch the printed text for room titles like `== Foothills ==`.
+  fn track_room(&mut self, c:char) {
+    if c != '\n' {
+      self.line.push(c);
       return;
     }
 
-    s.retain(|c| c != '\r');
-    self.inputs.extend(s.as_bytes());
+    if let Some(title) = self.line.strip_prefix("== ").and_then(|line| line.strip_suffix(" ==")) {
+      self.room = Some(title.to_string());
+    }
+    self.line.clear();
   }
 
   pub fn push_input(&mut self, s:String) {
+    self.transcribe_input(s.as_bytes());
     self.inputs.extend(s.as_bytes());
   }
-
-  ///Convert an [`OpCode`] into an [`OpCall`].
-  fn new_opcall(&self, op:OpCode, args:&Vec<u16>) -> OpCall {
-    //Get the PC of the OpCode called
-    let pc = self.pc - (args.len() + 1);
-
-    //Get the last item on the stack or mark it as -1 for Empty
-    let stack = self.stack.clone();
-
-    OpCall::new(op, pc, args.clone(), self.reg, self.stack.len(), stack)
-  }
 }
 
 //Opcode implementations
@@ -286,92 +615,63 @@ impl VM {
   #[allow(non_snake_case)]
   ///Takes 0 arguments. Stops execution, resets the program counter, and
   /// terminates the program.
-  pub fn Halt(&mut self) -> Result<OpCall> {
+  pub fn Halt(&mut self) -> Result<(), VMErrors> {
+    if self.running {
+      self.journal(Effect::Halt);
+    }
     self.pc = 0;
     self.running = false;
-
-    //Return the OpCall
-    let call = self.new_opcall(OpCode::Halt, &Vec::new());
-    Ok(call)
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 2 arguments. Set register indicated by the first argument equal to
   /// the second argument;
-  pub fn Set(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-
-    b = self.get_register_value(b);
+  pub fn Set(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
 
     //Set register a equal to value b
-    self.reg[a as usize] = b;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Set, &args);
-    Ok(call)
+    self.set_register(a, b);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 1 argument. Pushes the argument onto the stack.
-  pub fn Push(&mut self) -> Result<OpCall> {
-    //Get the argument
-    let args = self.get_args(1);
-    let mut a = args[0];
-
-    a = self.get_register_value(a);
+  pub fn Push(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
 
     //Push the argument onto the stack
-    self.stack.push(a);
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Push, &args);
-    Ok(call)
+    self.push_stack(a);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 1 argument. Removes the last element from the stack and writes it
   /// into the register indicated by the argument.
-  pub fn Pop(&mut self) -> Result<OpCall> {
-    //Get the argument
-    let args = self.get_args(1);
-    let a = args[0] % WORDSIZE;
+  pub fn Pop(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
 
     //Get the last element of on the stack
-    let val = self.stack.pop();
+    let val = self.pop_stack()?;
 
-    match val {
-      //Write the value removed from the stack into the register indicated by a
-      Some(val) => self.reg[a as usize] = val,
-      None => return Err(VMErrors::EmptyStack.into())
-    }
-
-    //Create and return the OpCall
-    let call = { self.new_opcall(OpCode::Pop, &args) };
-    Ok(call)
+    //Write the value removed from the stack into the register indicated by a
+    self.set_register(a, val);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 3 arguments. Sets the register the first argument indicates
   /// equal to 1 if the second and third arguments are equal. Otherwise, sets
   /// the value of the register the first argument indicates to 0.
-  pub fn Eq(&mut self) -> Result<OpCall> {
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn Eq(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
     //Perform the comparison and set the register indicated by a to the result
-    self.reg[a as usize] = (b == c) as u16;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Eq, &args);
-    Ok(call)
+    self.set_register(a, (b == c) as u16);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
@@ -379,410 +679,391 @@ impl VM {
   /// to 1 if the second argument's value is greater than third argument's
   /// value. Sets the register indicated by the first argument equal to 0 if the
   /// second argument's value is not greater than third argument's value.
-  pub fn Gt(&mut self) -> Result<OpCall> {
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn Gt(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
     //Perform the comparison and set the register indicated by a to the result
-    self.reg[a as usize] = (b > c) as u16;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Gt, &args);
-    Ok(call)
+    self.set_register(a, (b > c) as u16);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes in 1 argument. Sets the program counter to the argument.
-  pub fn Jmp(&mut self) -> Result<OpCall> {
-    //Get the argument
-    let args = self.get_args(1);
-
-    let mut a = args[0];
-    a = self.get_register_value(a);
+  pub fn Jmp(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
 
     //Set the program counter to the memory address indicated by a
     self.pc = a as usize;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Jmp, &args);
-    Ok(call)
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes in 2 arguments. Sets the program counter to the value of the second
   /// argument if the first argument is nonzero.
-  pub fn Jt(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-
-    let mut a = args[0];
-    let mut b = args[1];
-    a = self.get_register_value(a);
+  pub fn Jt(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
 
     //Set the PC to b's value if a is nonzero
     if a != 0 {
       //Set the program counter to the memory address indicated by b
-      b = self.get_register_value(b);
+      let b = self.get_register_value(args[1]);
       self.pc = b as usize;
     }
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Jt, &args);
-    Ok(call)
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes in 2 arguments. Sets the program counter to the second argument if
   /// the first argument is zero.
-  pub fn Jf(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-    let mut a = args[0];
-    let mut b = args[1];
-
-    a = self.get_register_value(a);
+  pub fn Jf(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
 
     //Set the PC to b's value if a is zero
     if a == 0 {
       //Set the program counter to the memory address indicated by b
-      b = self.get_register_value(b);
-
+      let b = self.get_register_value(args[1]);
       self.pc = b as usize;
     }
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Jf, &args);
-    Ok(call)
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes in 3 arguments. Stores the result of summing the second and third
   /// arguments' values (modulo WORDSIZE) in the register indicated by the first
   /// argument.
-  pub fn Add(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn Add(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
-    //Add c to b
-    let sum = (b + c) % WORDSIZE;
+    //Add c to b. Values read from memory can be 32768 or more so the sum is
+    //taken in u32
+    let sum = ((b as u32 + c as u32) % WORDSIZE as u32) as u16;
 
     //Store sum in the register indicated by a
-    self.reg[a as usize] = sum;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Add, &args);
-    Ok(call)
+    self.set_register(a, sum);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes in 3 arguments. Stores the product of the second and
   /// third arguments' values (modulo WORDSIZE) in the register indicated by the
   /// first argument.
-  pub fn Mult(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn Mult(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
     //Multiply c and b
     let prod = (b as u32 * c as u32) as u16 % WORDSIZE;
 
     //Store the product in the register indicated by a
-    self.reg[a as usize] = prod;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Mult, &args);
-    Ok(call)
+    self.set_register(a, prod);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 3 arguments. Stores the remainder of the second argument divided by
   /// the third argument in the register indicated by the first argument.
-  pub fn Mod(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  /// Errors if the third argument is zero.
+  pub fn Mod(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
+    if c == 0 {
+      return Err(VMErrors::DivisionByZero);
+    }
 
     //Calculate the quotient
     //No need to divide by WORDSIZE because b can never b > WORDSIZE
     let quot = b % c;
 
     //Store the quotient in the register indicated by a
-    self.reg[a as usize] = quot;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Mod, &args);
-    Ok(call)
+    self.set_register(a, quot);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 3 arguments. Stores the value of the bitwise `AND` of the second and
   /// third arguments' values in the register indicated by the first
   /// arguement.
-  pub fn And(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn And(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
     //Calculate the value
     let val = b & c;
 
     //Store value in the register indicated by a
-    self.reg[a as usize] = val;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::And, &args);
-    Ok(call)
+    self.set_register(a, val);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 3 values. Stores the value of the bitwise `OR` of the second and
   /// third arguments' values in the register indicated by the first
   /// arguement.
-  pub fn Or(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(3);
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    let mut c = args[2];
-
-    b = self.get_register_value(b);
-    c = self.get_register_value(c);
+  pub fn Or(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
+    let c = self.get_register_value(args[2]);
 
     //Calculate the value
     let val = b | c;
 
     //Store value in the register indicated by a
-    self.reg[a as usize] = val;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Or, &args);
-    Ok(call)
+    self.set_register(a, val);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 2 arguments. Stores the value of the 15-bit bitwise `INVERSE` of the
   /// second argument's value in the register indicated by the first
   /// arguement.
-  pub fn Not(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    b = self.get_register_value(b);
+  pub fn Not(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
 
     //Calculate the value
     //Modulo by WORDSIZE to get the 15-bit inverse
     let val = !b % WORDSIZE;
 
     //Store value in the register indicated by a
-    self.reg[a as usize] = val;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Not, &args);
-    Ok(call)
+    self.set_register(a, val);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 2 arguments. Reads memory from the memory address indicated by the
   /// second argument into the register indicated by the first argument.
-  pub fn Rmem(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-
-    let a = args[0] % WORDSIZE;
-    let mut b = args[1];
-    b = self.get_register_value(b);
+  pub fn Rmem(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
+    let b = self.get_register_value(args[1]);
 
     //Read from the address b
-    let val = self.mem[b as usize];
+    let val = self.read_mem(b)?;
 
     //Store the value in the register indicated by a
-    self.reg[a as usize] = val;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Rmem, &args);
-    Ok(call)
+    self.set_register(a, val);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 2 arguments. Writes the value of the
   /// second argument into the memory address indicated by the first argument.
-  pub fn Wmem(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(2);
-    let mut a = args[0];
-    let mut b = args[1];
-
-    a = self.get_register_value(a);
-    b = self.get_register_value(b);
+  pub fn Wmem(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
+    let b = self.get_register_value(args[1]);
 
     //Store b in address a
-    self.mem[a as usize] = b;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Wmem, &args);
-    Ok(call)
+    self.write_mem(a, b)
   }
 
   #[allow(non_snake_case)]
   ///Takes 1 argument. Writes the address of the next instruction to the stack
   /// then set the program counter to the memory address indicated by the
   /// argument.
-  pub fn Call(&mut self) -> Result<OpCall> {
-    //Get the args
-    let args = self.get_args(1);
-
-    let mut a = args[0];
-    a = self.get_register_value(a);
+  pub fn Call(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
 
     //Push the instruction of the next address to the stack
     let next = self.pc;
-    self.stack.push(next as u16);
+    self.push_stack(next as u16);
 
     //Set the program counter to the address indicated by a
     self.pc = a as usize;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Call, &args);
-    Ok(call)
+    self.journal(Effect::Enter);
+    self.frames.push(self.pc);
+    if let Some(profiler) = &mut self.profiler {
+      profiler.enter(self.pc);
+    }
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 0 arguments. Remove the top value from the stack and jump to it.
-  /// Panics if the stack is empty.
-  pub fn Ret(&mut self) -> Result<OpCall> {
+  /// Errors if the stack is empty.
+  pub fn Ret(&mut self) -> Result<(), VMErrors> {
     //Get the last element from the stack
-    let val = self.stack.pop();
+    let val = self.pop_stack()?;
 
     //Jump to the memory address indicated by the value
-    //Halt if the stack is empty
-    match val {
-      Some(val) => self.pc = val as usize,
-      None => return Err(VMErrors::EmptyStack.into())
+    self.pc = val as usize;
+    let frame = self.frames.pop();
+    //Entry addresses are memory addresses so they fit in a u16
+    self.journal(Effect::Leave(frame.map(|frame| frame as u16)));
+    if let (Some(profiler), Some(frame)) = (&mut self.profiler, frame) {
+      profiler.leave(frame);
     }
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Ret, &Vec::new());
-    Ok(call)
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 1 argument. Prints the next character represented by the argument's
-  /// ascii representation to the terminal.
-  pub fn Out(&mut self) -> Result<OpCall> {
-    //Get the arguments
-    let args = self.get_args(1).clone();
-
-    let mut a = args[0];
-    a = self.get_register_value(a);
-
-    let character = char::from_u32(a as u32).unwrap();
-    print!("{character}");
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Out, &args);
-    Ok(call)
+  /// ascii representation to the [`VM`]'s [`Output`]. Errors if the value is
+  /// not an ascii character.
+  pub fn Out(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register_value(args[0]);
+
+    let character = u8::try_from(a).ok().filter(u8::is_ascii).ok_or(VMErrors::InvalidCharacter(a))? as char;
+    match &mut self.captured {
+      Some(captured) => captured.push(character),
+      None => self.output.write_char(character)
+    }
+    self.transcribe_output(character);
+    self.track_room(character);
+    Ok(())
   }
 
   #[allow(non_snake_case)]
   ///Takes 1 argument. Reads characters from the [`VM`]'s input field until a
-  /// linebreak is encountered.
-  pub fn In(&mut self) -> Result<OpCall> {
-    let args = self.get_args(1);
+  /// linebreak is encountered. Reads a new line from the [`VM`]'s [`Input`]
+  /// when the input field is empty and errors if the [`Input`] is exhausted.
+  pub fn In(&mut self, args:&[Operand]) -> Result<(), VMErrors> {
+    let a = self.get_register(args[0])?;
 
-    let a = args[0] % WORDSIZE;
+    if !self.mid_line {
+      self.checkpoint();
+    }
 
     //Read the input from memory
-    let s = self.inputs.pop_front();
-
-    match s {
-      Some(s) => self.reg[a as usize] = s as u16,
-      None => {
-        self.read_input();
-        let s = self.inputs.pop_front().unwrap();
-        self.reg[a as usize] = s as u16
+    if self.inputs.is_empty() {
+      //Point back at this In while reading so a save made by a system command
+      //resumes by reading a new line
+      self.pc -= OpCode::In.arity() + 1;
+      self.reading = true;
+      let read = self.read_input();
+
+      //A system command loaded a save or started a new game
+      if !std::mem::take(&mut self.reading) {
+        return Ok(());
+      }
+
+      //Read input can also halt the VM through a system command
+      if !read {
+        return match self.running {
+          true => Err(VMErrors::InputEof),
+          false => Ok(())
+        };
       }
+      self.pc += OpCode::In.arity() + 1;
     }
 
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::In, &args);
-    Ok(call)
+    if let Some(s) = self.inputs.pop_front() {
+      self.journal(Effect::Input(s, self.mid_line));
+      self.set_register(a, s as u16);
+      self.mid_line = s != b'\n';
+    }
+    Ok(())
   }
 
   #[allow(non_snake_case)]
-  ///No operation. Increments the program counter by 1.
-  pub fn Noop(&mut self) -> Result<OpCall> {
-    self.pc += 1;
-
-    //Create and return the OpCall
-    let call = self.new_opcall(OpCode::Noop, &Vec::new());
-    Ok(call)
+  ///No operation. The program counter moves on to the next instruction.
+  pub fn Noop(&mut self) -> Result<(), VMErrors> {
+    Ok(())
   }
 }
 
 //System implementations
 impl VM {
+  ///Parse and run a system command, printing any error to the [`VM`]'s
+  /// [`Output`].
   pub fn exe_system_commands(&mut self, s:String) {
-    let s = &s.as_str()[1..s.len() - 2];
+    match s.parse::<Command>() {
+      Ok(cmd) => match self.transcribe_command(&s, &cmd) {
+        Ok(()) => self.exe_command(cmd),
+        Err(err) => self.output.write_str(&format!("{err}\n"))
+      },
+      Err(err) => self.output.write_str(&format!("{err}\n"))
+    }
+  }
 
-    match s {
-      "s" => self.save(),
-      "q" => self.quit(),
-      "rq" => self.rage_quit(),
-      "fq" => self.force_quit(),
-      "ls" => self.load_save(),
-      "dbg" => self.debug(),
-      "print" => self.prt(),
-      "clear" => self.dbg_clear(),
-      "solve" => self.solve(),
-      "1115" => self.prt_mem_addr(1115),
-      "path" => self.path(),
-      _ => println!("{}", VMErrors::UnknownCommand(s))
+  ///Run a parsed system [`Command`].
+  pub fn exe_command(&mut self, cmd:Command) {
+    match cmd {
+      Command::Save(name) => self.save(name.as_deref().unwrap_or(SAVE)),
+      Command::Load(name) => self.load_save(name.as_deref().unwrap_or(SAVE)),
+      Command::Saves => {
+        let saves = self.fmt_saves();
+        self.output.write_str(&saves);
+      }
+      Command::DeleteSave(name) => self.delete_save(&name),
+      Command::StepBack(n) => self.travel(|vm| vm.step_back_n(n)),
+      Command::ReverseContinue => self.travel(VM::reverse_continue),
+      Command::Rewind(n) => {
+        let msg = match self.rewind(n) {
+          Ok(()) if n == 1 => String::from("Undid the last command\n"),
+          Ok(()) => format!("Rewound {n} commands\n"),
+          Err(err) => format!("{err}\n")
+        };
+        self.output.write_str(&msg);
+      }
+      Command::Quit => self.quit(),
+      Command::RageQuit => self.rage_quit(),
+      Command::ForceQuit => self.force_quit(),
+      Command::Debug => self.debug(),
+      Command::History(n) => self.print_history(n),
+      Command::Print => self.toggle_trace(),
+      Command::Clear => self.clear_trace(),
+      Command::Solve => self.solve(),
+      Command::Path => self.path(),
+      Command::Pause => self.debugger.pause(),
+      Command::Disasm { addr, count } => {
+        let listing = disassemble(&self.mem, addr.unwrap_or(self.pc), count.unwrap_or(10));
+        self.output.write_str(&listing);
+      }
+      Command::Mem { addr, count } => self.prt_mem_addr(addr, count),
+      Command::Reg { reg, val } => {
+        if let Some(val) = val {
+          //Registers always exist so the patch cannot fail
+          self.poke(Patch::Register { reg, val }).unwrap();
+        }
+        let reply = format!("r{reg}: {}\n", self.reg[reg as usize]);
+        self.output.write_str(&reply);
+      }
+      Command::Poke { addr, vals } => {
+        if let Err(err) = self.poke(Patch::Memory { addr, vals }) {
+          self.output.write_str(&format!("{err}\n"));
+        }
+      }
+      Command::Patches => {
+        let patches = self.patches.iter().enumerate().map(|(i, patch)| format!("{i}: {patch}\n")).collect::<String>();
+        self.output.write_str(&patches);
+      }
+      Command::Break(addr) => {
+        self.debugger.breakpoints.insert(addr);
+      }
+      Command::Unbreak(addr) => {
+        self.debugger.breakpoints.remove(&addr);
+      }
+      Command::Watch(watchpoint) => {
+        self.debugger.watchpoints.push(watchpoint);
+        self.output.write_str(&format!("Watching {watchpoint}\n"));
+      }
+      Command::Unwatch(i) if i < self.debugger.watchpoints.len() => {
+        self.debugger.watchpoints.remove(i);
+      }
+      Command::Unwatch(i) => self.output.write_str(&format!("No watchpoint {i}\n")),
+      Command::Watches => {
+        let watchpoints = self.fmt_watchpoints();
+        self.output.write_str(&watchpoints);
+      }
+      Command::Trace(setting) => self.set_trace_filter(setting),
+      Command::Profile(setting) => self.set_profiling(setting),
+      Command::Help => self.output.write_str(&help())
     }
   }
 
-  ///Generate a path for the room and appended it to inputs
+  ///Find the path through the vault and write it to the vault path file.
   fn path(&mut self) {
-    //Create the graph
-    let mut graph = Graph::new(8);
-    graph.add_node(0, 8, vec![(Sub, 1), (Sub, 3), (Mul, 3), (Mul, 4), (Mul, 2)]);
-    graph.add_node(1, 1, vec![(Mul, 5), (Mul, 3), (Sub, 0), (Sub, 3)]);
-    graph.add_node(2, 4, vec![(Mul, 0), (Mul, 3), (Mul, 4), (Add, 4)]);
-    graph.add_node(3, 11, vec![(Sub, 0), (Sub, 1), (Mul, 0), (Mul, 1), (Mul, 4), (Mul, 5), (Sub, 7), (Mul, 2), (Sub, 4), (Sub, 5)]);
-    graph.add_node(4, 4, vec![(Mul, 2), (Mul, 0), (Mul, 3), (Add, 2), (Sub, 3), (Sub, 5), (Sub, 7)]);
-    graph.add_node(5, 18, vec![(Sub, 7), (Sub, 4), (Sub, 3), (Mul, 3), (Mul, 1), (Mul, 7)]);
-    graph.add_node(6, 22, vec![(Sub, 7), (Sub, 4), (Add, 4), (Add, 2)]);
-    graph.add_node(7, 9, vec![(Sub, 4), (Sub, 3), (Sub, 5), (Mul, 5)]);
-
-    //Calculate the path
-    graph.get_shortest_path((6, 22), (1, 30));
+    let path = vault().get_shortest_path((6, 22), (1, 30));
+    let written = File::create(&self.config.vault_path).and_then(|mut file| write!(file, "{:?}", path.unwrap_or_default()));
+    if let Err(err) = written {
+      self.output.write_str(&format!("Could not write the path to {}: {err}\n", self.config.vault_path.display()));
+    }
   }
 
   ///Toggle the debug mode. Required for implementing other debug operations.
@@ -790,25 +1071,6 @@ impl VM {
     self.debug ^= DEBUG;
   }
 
-  ///Toggle print debug mode.
-  /// Each [`OpCode`] called will print to the standard output.
-  fn prt(&mut self) {
-    self.debug ^= PRINT;
-  }
-
-  fn debug_print(&self, file:&mut File, call:OpCall) {
-    //Create the new log
-    let log = call.to_string();
-
-    //Append new OpCall to the end of the debug file
-    write!(file, "{log}").unwrap();
-  }
-
-  fn dbg_clear(&self) {
-    let mut file = fs::File::create("debug_log.txt").unwrap();
-    file.write_all(&[]).unwrap();
-  }
-
   ///Quit the game without saving.
   fn force_quit(&mut self) {
     self.Halt().unwrap();
@@ -819,71 +1081,433 @@ impl VM {
     solver(self);
   }
 
-  ///Prints the [`OpCode`] of the provided memory address + the next 3 values.
-  fn prt_mem_addr(&mut self, addr:u16) {
-    let mut file = File::create("dbg_console.txt").unwrap();
-    let op = OpCode::new(self.mem[addr as usize]).unwrap();
-    let op = serde_json::to_string(&op).unwrap();
-    write!(file, "{op}").unwrap();
+  ///Prints the decoded [`Instruction`]s starting at the provided memory
+  /// address to the console file.
+  fn prt_mem_addr(&mut self, addr:u16, count:u16) {
+    let listing = disassemble(&self.mem, addr as usize, count as usize);
+    let written = File::create(&self.config.console).and_then(|mut file| write!(file, "{listing}"));
+    if let Err(err) = written {
+      self.output.write_str(&format!("Could not write to {}: {err}\n", self.config.console.display()));
+    }
   }
 
-  ///Quit the current game and start a new one.
+  ///Quit the current game and start a new one. The caller keeps running the
+  /// [`VM`] from the start of the new game. Keeps playing the current game if
+  /// the program cannot be loaded.
   fn rage_quit(&mut self) {
-    self.Halt().unwrap();
-    let new = VM::new();
-    *self = new;
-    self.load_new().unwrap();
-    self.run().unwrap();
+    let mut new = VM::new();
+    new.config = self.config.clone();
+    let msg = match new.load_new() {
+      Ok(()) => {
+        let image = new.image.clone();
+        self.replace(new);
+        self.image = image;
+        String::from("Started a new game\n")
+      }
+      Err(err) => format!("Could not start a new game: {err:#}\n")
+    };
+    self.output.write_str(&msg);
   }
 
   ///Save and quit the game.
   fn quit(&mut self) {
-    self.save();
+    self.save(SAVE);
     self.Halt().unwrap();
   }
 
-  ///Reload the current save.
-  fn load_save(&mut self) {
-    self.Halt().unwrap();
-    let new = VM::new();
-    *self = new;
-    self.load().unwrap();
+  ///Reload the current save or load the save with the provided name. Keeps
+  /// playing the current game if the save cannot be loaded.
+  fn load_save(&mut self, name:&str) {
+    let msg = match self.load_from(name) {
+      Ok(()) => format!("Loaded {name}\n"),
+      Err(err) => format!("Could not load {name}: {err}\n")
+    };
+    self.output.write_str(&msg);
+  }
+
+  fn save(&mut self, name:&str) {
+    let msg = match write_slot(self, &self.config.save_dir, name, self.config.save_format) {
+      Ok(()) => format!("Saved {name}\n"),
+      Err(err) => format!("Could not save {name}: {err}\n")
+    };
+    self.output.write_str(&msg);
   }
 
-  fn save(&self) {
-    let mut file = File::create("sync_save.json").unwrap();
-    let state = serde_json::to_string(&self).unwrap();
-    file.write_all(state.as_bytes()).unwrap();
+  ///Delete the save slot with the provided name.
+  fn delete_save(&mut self, name:&str) {
+    let msg = match find_slot(&self.config.save_dir, name).map(fs::remove_file) {
+      Some(Ok(())) => format!("Deleted {name}\n"),
+      Some(Err(err)) => format!("Could not delete {name}: {err}\n"),
+      None => format!("Could not delete {name}: {}\n", VMErrors::MissingSave(name.to_string()))
+    };
+    self.output.write_str(&msg);
+  }
+
+  ///List the save slots with when they were saved, how many instructions had
+  /// run and the room the game was in.
+  fn fmt_saves(&self) -> String {
+    let slots = match list_saves(&self.config.save_dir) {
+      Ok(slots) if slots.is_empty() => return format!("No saves in {}\n", self.config.save_dir.display()),
+      Ok(slots) => slots,
+      Err(err) => return format!("Could not list the saves in {}: {err}\n", self.config.save_dir.display())
+    };
+
+    let width = slots.iter().map(|slot| slot.name.len()).max().unwrap_or(0);
+    let mut s = format!("Saves in {}:\n", self.config.save_dir.display());
+    for slot in slots {
+      let line = match slot.header {
+        Ok(header) => format!(
+          "{}  {:>12} instructions  {}",
+          fmt_timestamp(header.saved_at),
+          header.cycles,
+          header.room.as_deref().unwrap_or("Unknown room")
+        ),
+        Err(err) => format!("unreadable: {err}")
+      };
+      s += &format!("  {:<width$}  {line}\n", slot.name);
+    }
+    s
   }
 
+  ///Resume the default save slot or start a new game if there is none.
   pub fn load(&mut self) -> Result<()> {
-    //Try to load from the save
-    let f = fs::read_to_string("sync_save.json");
-
-    match f {
-      Ok(s) => {
-        let state = serde_json::from_str::<VM>(&s)?;
-        let inputs = VecDeque::from([b'\n', b'l', b'o', b'o', b'k', b'\n']);
-
-        self.mem = state.mem;
-        self.stack = state.stack;
-        self.pc = state.pc;
-        self.reg = state.reg;
-        self.inputs = inputs;
-      }
-      Err(_) => self.load_new()?
+    self.load_or_new(SAVE)
+  }
+
+  ///Resume the save slot with the provided name, or start a new game if it
+  /// does not exist. Used when the [`VM`] starts.
+  pub fn load_or_new(&mut self, name:&str) -> Result<()> {
+    if find_slot(&self.config.save_dir, name).is_none() {
+      self.reset();
+      return self.load_new();
     }
+    self.load_from(name)
+  }
 
+  ///Load the save slot with the provided name, resuming exactly where it
+  /// was saved with its patches re-applied. Errors without changing the
+  /// [`VM`] if the save does not exist or cannot be read.
+  pub fn load_from(&mut self, name:&str) -> Result<()> {
+    let Some(path) = find_slot(&self.config.save_dir, name)
+    else {
+      return Err(VMErrors::MissingSave(name.to_string()).into());
+    };
+
+    let mut save = read_save(path)?;
+    save.vm.reapply_patches()?;
+    self.replace(save.vm);
+    self.load_image();
     Ok(())
   }
 
-  fn load_new(&mut self) -> Result<()> {
-    //Load the binary as a Vec<u8> then convert it to a slice of u16s
-    let bin = fs::read("challenge.bin")?;
-    let bin = unsafe { bin.align_to::<u16>().1 };
+  ///Read the program image checkpoints are taken against if no program has
+  /// been loaded, as when the game is resumed from a save. Without the
+  /// program checkpoints hold all of memory.
+  fn load_image(&mut self) {
+    if self.image.is_empty() {
+      if let Ok(bin) = fs::read(&self.config.bin) {
+        self.image = Arc::from(words(&bin));
+      }
+    }
+  }
+
+  ///Load the program binary for a new game.
+  pub fn load_new(&mut self) -> Result<()> {
+    let bin = self.config.bin.clone();
+    self.load_bin(&bin).wrap_err_with(|| format!("Could not load the program {}", bin.display()))
+  }
 
-    //Add the loaded binary to the stack
-    self.mem.extend_from_slice(bin);
+  ///Load a little-endian program binary into memory.
+  pub fn load_bin<P:AsRef<Path>>(&mut self, path:P) -> Result<()> {
+    //Load the binary as a Vec<u8> then convert it to u16s
+    let bin = fs::read(path)?;
+
+    //Add the loaded binary to the memory
+    self.mem.extend(words(&bin));
+    self.image = Arc::from(self.mem.as_slice());
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::{OpCall, OpCode, RunStatus, VM};
+  use crate::{
+    assembler::assemble,
+    errors::VMErrors,
+    io::{BufferInput, BufferOutput},
+    save::SaveFormat
+  };
+
+  #[test]
+  fn run_until_input() {
+    //Out '>', In R0, Out R0, Eq R1 R0 '\n', Jf R1 2, Jmp 0
+    let mut vm = VM::new();
+    vm.mem = vec![19, 62, 20, 32768, 19, 32768, 4, 32769, 32768, 10, 8, 32769, 2, 6, 0];
+
+    let outcome = vm.run_until_input(1000);
+    assert!(matches!(outcome.status, RunStatus::WaitingForInput));
+    assert_eq!(outcome.output, ">");
+
+    vm.push_input(String::from("look\n"));
+    let outcome = vm.run_until_input(1000);
+    assert!(matches!(outcome.status, RunStatus::WaitingForInput));
+    assert_eq!(outcome.output, "look\n>");
+  }
+
+  #[test]
+  fn run_until_input_status() {
+    //Out 'x', Halt
+    let mut vm = VM::new();
+    vm.mem = vec![19, 120, 0];
+    let outcome = vm.run_until_input(1000);
+    assert!(matches!(outcome.status, RunStatus::Halted));
+    assert_eq!(outcome.output, "x");
+
+    //Jmp 0
+    let mut vm = VM::new();
+    vm.mem = vec![6, 0];
+    let outcome = vm.run_until_input(10);
+    assert!(matches!(outcome.status, RunStatus::OutOfCycles));
+
+    let mut vm = VM::new();
+    vm.mem = vec![99];
+    let outcome = vm.run_until_input(10);
+    assert!(matches!(outcome.status, RunStatus::Faulted(_)));
+  }
+
+  #[test]
+  fn faults() {
+    let fault = |mem:Vec<u16>| {
+      let mut vm = VM::new();
+      vm.mem = mem;
+      vm.config.core = None;
+      vm.set_input(BufferInput::new(Vec::<String>::new()));
+      vm.run().unwrap_err()
+    };
+
+    //Push 7, Set 5 1
+    let err = fault(vec![2, 7, 1, 5, 1]);
+    assert_eq!(err.kind, VMErrors::InvalidRegister(5));
+    assert_eq!(err.pc, 2);
+    assert_eq!(err.instruction.unwrap().op, OpCode::Set);
+    assert_eq!(err.stack_top, [7]);
+
+    //Mod R0 5 0
+    assert_eq!(fault(vec![11, 32768, 5, 0]).kind, VMErrors::DivisionByZero);
+    //Rmem R0 100
+    assert_eq!(fault(vec![15, 32768, 100]).kind, VMErrors::OutOfBounds(100));
+    //Out 200
+    assert_eq!(fault(vec![19, 200]).kind, VMErrors::InvalidCharacter(200));
+    //In R0
+    assert_eq!(fault(vec![20, 32768]).kind, VMErrors::InputEof);
+
+    //Noop, 99
+    let err = fault(vec![21, 99]);
+    assert_eq!(err.kind, VMErrors::UnknownOpcode(99));
+    assert_eq!(err.pc, 1);
+    assert!(err.instruction.is_none());
+  }
+
+  #[test]
+  fn add_overflow() {
+    //Rmem R0 8, Add R1 R0 R0, Halt, 40000
+    let mut vm = VM::new();
+    vm.mem = vec![15, 32768, 8, 9, 32769, 32768, 32768, 0, 40000];
+    vm.run().unwrap();
+    assert_eq!(vm.reg[1], (80000 % 32768) as u16);
+  }
+
+  #[test]
+  fn fault_report() {
+    //Set R3 9, Mod R0 R3 R1
+    let mut vm = VM::new();
+    vm.mem = vec![1, 32771, 9, 11, 32768, 32771, 32769];
+    vm.config.core = None;
+    let report = vm.run().unwrap_err().to_string();
+    assert!(report.starts_with("Fault at pc 3 executing `Mod r0 r3 r1`: Tried to divide by zero.\n"));
+    assert!(report.contains("r3: 9"));
+  }
+
+  #[test]
+  fn command_errors() {
+    let dir = std::env::temp_dir().join("vm_command_errors_test");
+    let mut vm = VM::new();
+    vm.mem = assemble("in r0\nhalt").unwrap();
+    vm.config.core = None;
+    vm.config.console = dir.join("missing/console.txt");
+    vm.config.vault_path = dir.join("missing/path.txt");
+    vm.config.bin = dir.join("missing.bin");
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["*mem 0 2", "*path", "*rq"]));
+    vm.set_output(out.clone());
+
+    //The game keeps running after every command fails
+    assert_eq!(vm.run().unwrap_err().kind, VMErrors::InputEof);
+    let out = out.contents();
+    assert!(out.contains("Could not write to "));
+    assert!(out.contains("Could not write the path to "));
+    assert!(out.contains("Could not start a new game: Could not load the program "));
+  }
+
+  #[test]
+  fn rage_quit() {
+    let dir = std::env::temp_dir().join("vm_rage_quit_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    //Out 'n', Halt
+    std::fs::write(dir.join("new.bin"), [19, 0, 110, 0, 0, 0]).unwrap();
+
+    let mut vm = VM::new();
+    vm.mem = assemble("in r0\nhalt").unwrap();
+    vm.config.bin = dir.join("new.bin");
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["*rq", "never read"]));
+    vm.set_output(out.clone());
+
+    //The new game runs in the same loop instead of reading more input
+    vm.run().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out.contents(), "Started a new game\nn");
+    assert_eq!(vm.mem, [19, 110, 0]);
+  }
+
+  #[test]
+  fn load_missing() {
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nhalt").unwrap();
+    vm.config.save_dir = std::env::temp_dir().join("vm_load_missing_test");
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    vm.step().unwrap();
+    vm.exe_system_commands(String::from("*load typo"));
+
+    //The current game keeps playing
+    assert_eq!(out.contents(), "Could not load typo: There is no save named `typo`.\n");
+    assert_eq!((vm.pc, vm.reg[0], vm.mem.len()), (3, 1, 4));
+  }
+
+  #[test]
+  fn resumed_image() {
+    let dir = std::env::temp_dir().join("vm_resumed_image_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    //Set r0 1, Halt, then a large program
+    let mut bin = [1, 0, 0, 128, 1, 0, 0, 0].to_vec();
+    bin.resize(2000, 7);
+    std::fs::write(dir.join("game.bin"), bin).unwrap();
+
+    let mut vm = VM::new();
+    vm.config.bin = dir.join("game.bin");
+    vm.config.save_dir = dir.clone();
+    vm.load_new().unwrap();
+    vm.step().unwrap();
+    vm.exe_system_commands(String::from("*save slot"));
+
+    //Checkpoints of the resumed game are deltas against the program
+    let mut resumed = VM::new();
+    resumed.config = vm.config.clone();
+    resumed.load_or_new("slot").unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(resumed.image, vm.image);
+    assert_eq!((resumed.pc, resumed.reg[0]), (3, 1));
+    assert!(resumed.snapshot().len() < resumed.to_snapshot(None).len() / 10);
+  }
+
+  #[test]
+  fn save_while_reading() {
+    let dir = std::env::temp_dir().join("vm_resume_test");
+
+    //Out '>', In R0, Out R0, Eq R1 R0 '\n', Jf R1 2, Jmp 0
+    let mut vm = VM::new();
+    vm.mem = vec![19, 62, 20, 32768, 19, 32768, 4, 32769, 32768, 10, 8, 32769, 2, 6, 0];
+    vm.config.core = None;
+    vm.config.save_dir = dir.clone();
+    let out = BufferOutput::new();
+    vm.set_input(BufferInput::new(["a", "*save slot", "b", "*load slot", "c"]));
+    vm.set_output(out.clone());
+
+    //The load resumes at the In which was reading when the game was saved
+    assert_eq!(vm.run().unwrap_err().kind, VMErrors::InputEof);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out.contents(), ">a\n>Saved slot\nb\n>Loaded slot\nc\n>");
+    //The load also rolled back the instructions run after the save
+    assert_eq!(vm.cycles, 23);
+  }
+
+  #[test]
+  fn binary_slot() {
+    let dir = std::env::temp_dir().join("vm_binary_slot_test");
+
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 1\nset r0 2\nhalt").unwrap();
+    vm.config.save_dir = dir.clone();
+    vm.config.save_format = SaveFormat::Binary;
+    let out = BufferOutput::new();
+    vm.set_output(out.clone());
+    vm.step().unwrap();
+    vm.exe_system_commands(String::from("*save slot"));
+    vm.step().unwrap();
+    vm.exe_system_commands(String::from("*load slot"));
+    vm.exe_system_commands(String::from("*saves"));
+
+    let saved = std::fs::read(dir.join("slot.bin"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(saved.unwrap().starts_with(b"SVMS"));
+    assert_eq!((vm.pc, vm.reg[0], vm.cycles), (3, 1, 1));
+    let out = out.contents();
+    assert!(out.starts_with("Saved slot\nLoaded slot\nSaves in "));
+    assert!(out.contains("  slot  "));
+  }
+
+  #[test]
+  fn resumed_cycle_limit() {
+    let dir = std::env::temp_dir().join("vm_resumed_cycle_limit_test");
+
+    //Jmp 0
+    let mut vm = VM::new();
+    vm.mem = vec![6, 0];
+    vm.cycles = 702455;
+    vm.config.save_dir = dir.clone();
+    vm.set_output(BufferOutput::new());
+    vm.exe_system_commands(String::from("*save slot"));
+
+    //The limit counts the instructions run since the VM started, not the save's
+    let mut resumed = VM::new();
+    resumed.config = vm.config.clone();
+    resumed.config.core = None;
+    resumed.cycle_limit = Some(10);
+    resumed.load_or_new("slot").unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(resumed.run().unwrap_err().kind, VMErrors::CycleLimit(10));
+    assert_eq!(resumed.cycles, 702465);
+  }
+
+  #[test]
+  fn trace() {
+    let path = std::env::temp_dir().join("vm_trace_test.jsonl");
+
+    let mut vm = VM::new();
+    vm.mem = assemble("set r0 5\npush r0\nwmem 100 r0\ncall 11\nhalt\nret").unwrap();
+    vm.mem.resize(101, 0);
+    vm.set_output(BufferOutput::new());
+    vm.trace_to(&path).unwrap();
+    vm.dbg_run().unwrap();
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let calls = trace.lines().map(|line| serde_json::from_str(line).unwrap()).collect::<Vec<OpCall>>();
+    assert_eq!(
+      calls.iter().map(|call| call.op).collect::<Vec<OpCode>>(),
+      [OpCode::Set, OpCode::Push, OpCode::Wmem, OpCode::Call, OpCode::Ret, OpCode::Halt]
+    );
+    assert_eq!(
+      (calls[0].raw.as_slice(), calls[0].args.as_slice(), calls[0].regs.as_slice()),
+      ([32768, 5].as_slice(), [0, 5].as_slice(), [(0, 5)].as_slice())
+    );
+    assert_eq!(calls[2].mem, [(100, 5)]);
+    assert_eq!((calls[3].pc, calls[3].pushed.as_slice()), (8, [10].as_slice()));
+    assert_eq!(calls[4].popped, [10]);
+
+    //Only the changes are written
+    assert_eq!(trace.lines().nth(1).unwrap(), r#"{"pc":3,"op":"Push","raw":[32768],"args":[5],"pushed":[5]}"#);
+  }
+}